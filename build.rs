@@ -0,0 +1,61 @@
+//! Lists the files in `assets/` for the `embed-assets` feature.
+//! Without the feature the list is empty and assets are read from disk.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// Written by the game, not part of the assets
+const SKIPPED: &[&str] = &[
+    "assets/scores.txt",
+    "assets/skin.txt",
+    "assets/settings.toml",
+    "assets/stats.toml",
+    "assets/achievements.toml",
+    "assets/profiles.toml",
+    // Everything saved per player
+    "assets/profiles",
+];
+
+fn collect(dir: &Path, files: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(name) = path.to_str() else {
+            continue;
+        };
+        // Same separators as the paths used in the game
+        let name = name.replace('\\', "/");
+        if SKIPPED.contains(&name.as_str()) {
+            continue;
+        }
+        if path.is_dir() {
+            collect(&path, files);
+        } else {
+            files.push(name);
+        }
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=assets");
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_EMBED_ASSETS");
+
+    let mut files = Vec::new();
+    if env::var_os("CARGO_FEATURE_EMBED_ASSETS").is_some() {
+        collect(Path::new("assets"), &mut files);
+        files.sort();
+    }
+
+    let root = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let mut code = String::from("pub static EMBEDDED: &[(&str, &[u8])] = &[\n");
+    for file in &files {
+        let absolute = Path::new(&root).join(file);
+        code += &format!("    ({:?}, include_bytes!({:?})),\n", file, absolute);
+    }
+    code += "];\n";
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("embedded_assets.rs");
+    fs::write(out, code).unwrap();
+}
@@ -0,0 +1,183 @@
+use crate::food::FoodVariant;
+use crate::manifest::{AchievementEntry, Stat};
+use crate::stats::LifetimeStats;
+use crate::storage::{load_toml, save_toml};
+use crate::summary::RunSummary;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+/// The value of `stat` while the game `run` is going on, `stats` doesn't contain it yet.
+/// Games are only counted once they are finished.
+pub fn stat_value(stat: Stat, run: &RunSummary, stats: &LifetimeStats) -> f32 {
+    match stat {
+        Stat::Length => run.max_length as f32,
+        Stat::TimeAlive => run.time_alive,
+        Stat::UnboostedTime => run.unboosted_time,
+        Stat::AxesWrapped => run.axes_wrapped() as f32,
+        Stat::ApplesEaten => (stats.apples + run.eaten(FoodVariant::Normal)) as f32,
+        Stat::PoopEaten => (stats.poop + run.eaten(FoodVariant::Poop)) as f32,
+        Stat::GamesPlayed => stats.games_played as f32,
+        Stat::Distance => stats.distance + run.distance,
+    }
+}
+
+/** How far the player got with every achievement, saved in `achievements.toml` in the profile directory.
+Achievements are defined in the manifest, only the best value of their stat is kept
+here, so goals can change without losing progress.
+*/
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AchievementProgress {
+    pub best: BTreeMap<String, f32>,
+}
+
+impl AchievementProgress {
+    const FILE: &'static str = "achievements.toml";
+
+    pub fn load(dir: &Path) -> Self {
+        load_toml(&dir.join(Self::FILE))
+    }
+
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        save_toml(&dir.join(Self::FILE), self)
+    }
+
+    pub fn value(&self, id: &str) -> f32 {
+        self.best.get(id).copied().unwrap_or(0.0)
+    }
+
+    pub fn is_unlocked(&self, id: &str, achievement: &AchievementEntry) -> bool {
+        self.value(id) >= achievement.goal
+    }
+
+    /// Keeps the best values of the running game, returns the names of the
+    /// achievements unlocked just now.
+    pub fn update(
+        &mut self,
+        achievements: &BTreeMap<String, AchievementEntry>,
+        run: &RunSummary,
+        stats: &LifetimeStats,
+    ) -> Vec<String> {
+        let mut unlocked = Vec::new();
+        for (id, achievement) in achievements {
+            let value = stat_value(achievement.stat, run, stats);
+            let best = self.value(id);
+            if value <= best {
+                continue;
+            }
+            if best < achievement.goal && value >= achievement.goal {
+                unlocked.push(achievement.name.clone());
+            }
+            self.best.insert(id.clone(), value);
+        }
+        unlocked
+    }
+
+    /// Like `update` once the game is over and added to `stats`, for the
+    /// achievements that count finished games.
+    pub fn update_finished(
+        &mut self,
+        achievements: &BTreeMap<String, AchievementEntry>,
+        stats: &LifetimeStats,
+    ) -> Vec<String> {
+        // Nothing of the game is left to add
+        self.update(achievements, &RunSummary::new(0, 0), stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{Events, GameEvent};
+    use macroquad::math::Vec3;
+
+    fn achievement(name: &str, stat: Stat, goal: f32) -> AchievementEntry {
+        AchievementEntry {
+            name: String::from(name),
+            description: String::new(),
+            stat,
+            goal,
+        }
+    }
+
+    #[test]
+    fn test_unlock_once() {
+        let achievements = BTreeMap::from([
+            (String::from("long"), achievement("Long", Stat::Length, 5.0)),
+            (
+                String::from("poop"),
+                achievement("Recycler", Stat::PoopEaten, 3.0),
+            ),
+        ]);
+        let stats = LifetimeStats {
+            poop: 2,
+            ..Default::default()
+        };
+        let mut progress = AchievementProgress::default();
+        let mut run = RunSummary::new(0, 3);
+        let mut events = Events::new();
+        events.push(GameEvent::FoodEaten {
+            variant: FoodVariant::Poop,
+            quality: 1,
+        });
+        run.record(&events, Vec3::ZERO, 4, 0.1);
+
+        // Lifetime stats and this game together
+        assert_eq!(
+            progress.update(&achievements, &run, &stats),
+            vec!["Recycler"]
+        );
+        assert!(progress.is_unlocked("poop", &achievements["poop"]));
+        assert!(!progress.is_unlocked("long", &achievements["long"]));
+        assert_eq!(progress.value("long"), 4.0);
+
+        events.clear();
+        run.record(&events, Vec3::ZERO, 6, 0.1);
+        assert_eq!(progress.update(&achievements, &run, &stats), vec!["Long"]);
+        // Nothing new
+        assert!(progress.update(&achievements, &run, &stats).is_empty());
+    }
+
+    #[test]
+    fn test_games_played() {
+        let achievements = BTreeMap::from([(
+            String::from("regular"),
+            achievement("Regular", Stat::GamesPlayed, 2.0),
+        )]);
+        let mut stats = LifetimeStats {
+            games_played: 1,
+            apples: 4,
+            ..Default::default()
+        };
+        let mut progress = AchievementProgress::default();
+        let mut run = RunSummary::new(0, 3);
+        let mut events = Events::new();
+        events.push(GameEvent::FoodEaten {
+            variant: FoodVariant::Normal,
+            quality: 1,
+        });
+        run.record(&events, Vec3::ZERO, 4, 0.1);
+
+        // The second game is still going on
+        assert!(progress.update(&achievements, &run, &stats).is_empty());
+        stats.add_run(&run);
+        assert_eq!(
+            progress.update_finished(&achievements, &stats),
+            vec!["Regular"]
+        );
+        assert_eq!(progress.value("regular"), 2.0);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut progress = AchievementProgress::default();
+        progress.best.insert(String::from("long_shnek"), 12.0);
+        let text = toml::to_string(&progress).unwrap();
+        assert_eq!(
+            toml::from_str::<AchievementProgress>(&text).unwrap(),
+            progress
+        );
+    }
+}
@@ -0,0 +1,356 @@
+use macroquad::audio::{load_sound_from_bytes, Sound};
+use macroquad::color::{Color, BLACK, MAGENTA};
+use macroquad::texture::{FilterMode, Image, Texture2D};
+use std::borrow::Cow;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// Generated by build.rs, empty unless the `embed-assets` feature is enabled
+include!(concat!(env!("OUT_DIR"), "/embedded_assets.rs"));
+
+/// Why an asset could not be loaded.
+#[derive(Debug)]
+pub enum AssetError {
+    /// The file is missing or can't be read.
+    File {
+        path: String,
+        message: String,
+    },
+    Model {
+        path: String,
+        error: tobj::LoadError,
+    },
+    Materials {
+        path: String,
+        error: tobj::LoadError,
+    },
+    Gltf {
+        path: String,
+        message: String,
+    },
+    /// A model part without a material, or a material without a diffuse texture.
+    NoTexture {
+        path: String,
+        material: String,
+    },
+    Image {
+        path: String,
+        error: image::ImageError,
+    },
+    InvalidMesh {
+        model: String,
+        reason: &'static str,
+    },
+    Font {
+        path: String,
+        message: String,
+    },
+    Sound {
+        path: String,
+        message: String,
+    },
+    Manifest {
+        path: String,
+        message: String,
+    },
+    /// The game needs an asset id the manifest does not have.
+    UnknownId(String),
+    /// The GPU driver rejected a shader, things are drawn without it.
+    Shader {
+        name: &'static str,
+        message: String,
+    },
+}
+
+impl AssetError {
+    /// Turns a macroquad loading error into an asset error for `path`.
+    pub fn from_macroquad(path: &str, error: macroquad::Error) -> Self {
+        let message = match error {
+            macroquad::Error::FileError { kind, .. } => kind.to_string(),
+            macroquad::Error::FontError(message) => message.to_string(),
+            error => error.to_string(),
+        };
+        AssetError::File {
+            path: path.to_string(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::File { path, message } => write!(f, "{}: {}", path, message),
+            AssetError::Model { path, error } => write!(f, "{}: {}", path, error),
+            AssetError::Materials { path, error } => {
+                write!(f, "{}: materials: {}", path, error)
+            }
+            AssetError::NoTexture { path, material } => {
+                write!(f, "{}: no texture for material '{}'", path, material)
+            }
+            AssetError::Gltf { path, message } => write!(f, "{}: {}", path, message),
+            AssetError::Image { path, error } => write!(f, "{}: {}", path, error),
+            AssetError::InvalidMesh { model, reason } => write!(f, "mesh '{}': {}", model, reason),
+            AssetError::Font { path, message } => write!(f, "{}: {}", path, message),
+            AssetError::Sound { path, message } => write!(f, "{}: {}", path, message),
+            AssetError::Manifest { path, message } => write!(f, "{}: {}", path, message.trim()),
+            AssetError::UnknownId(id) => write!(f, "'{}' is missing in the manifest", id),
+            AssetError::Shader { name, message } => write!(f, "{} shader: {}", name, message),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+/** Where asset files are read from. Paths look like `assets/apfel/apfel.obj`
+and always use `/`. A file in the override directory wins over an embedded file,
+which wins over the file relative to the working directory.
+*/
+#[derive(Debug, Default)]
+pub struct Assets {
+    /// Replaces the `assets` directory, for mods.
+    override_dir: Option<PathBuf>,
+}
+
+impl Assets {
+    pub fn new(override_dir: Option<PathBuf>) -> Self {
+        Self { override_dir }
+    }
+
+    /// Override directory from `--assets <dir>` or the `SHNEK_ASSETS` environment variable.
+    /// With `--dev` the `assets` directory is read even if the assets are embedded,
+    /// so changed files can be reloaded.
+    pub fn from_args() -> Self {
+        let args: Vec<String> = std::env::args().collect();
+        let from_args = args
+            .iter()
+            .position(|arg| arg == "--assets")
+            .and_then(|i| args.get(i + 1))
+            .map(PathBuf::from);
+        let from_env = std::env::var_os("SHNEK_ASSETS").map(PathBuf::from);
+        let dev = args
+            .iter()
+            .any(|arg| arg == "--dev")
+            .then(|| PathBuf::from("assets"));
+        Self::new(from_args.or(from_env).or(dev))
+    }
+
+    pub fn override_dir(&self) -> Option<&Path> {
+        self.override_dir.as_deref()
+    }
+
+    pub fn embedded(path: &str) -> Option<&'static [u8]> {
+        EMBEDDED
+            .iter()
+            .find(|(name, _)| *name == path)
+            .map(|(_, data)| *data)
+    }
+
+    pub fn read(&self, path: &str) -> Result<Cow<'static, [u8]>, AssetError> {
+        if let Some(dir) = &self.override_dir {
+            let relative = path.strip_prefix("assets/").unwrap_or(path);
+            if let Ok(data) = std::fs::read(dir.join(relative)) {
+                return Ok(Cow::Owned(data));
+            }
+        }
+        if let Some(data) = Self::embedded(path) {
+            return Ok(Cow::Borrowed(data));
+        }
+        std::fs::read(path)
+            .map(Cow::Owned)
+            .map_err(|error| AssetError::File {
+                path: path.to_string(),
+                message: error.to_string(),
+            })
+    }
+}
+
+/// Path of a file referenced by `path`, like a texture named in a material file.
+pub fn sibling(path: &str, name: &str) -> String {
+    match Path::new(path).parent().and_then(Path::to_str) {
+        Some(dir) if !dir.is_empty() => format!("{}/{}", dir, name.replace('\\', "/")),
+        _ => name.replace('\\', "/"),
+    }
+}
+
+/** Everything that failed while loading, shown on the startup screen.
+Loading goes on after a failure, missing pieces are replaced by placeholders.
+*/
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    pub failures: Vec<AssetError>,
+}
+
+impl LoadReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, error: AssetError) {
+        self.failures.push(error);
+    }
+
+    /// Keeps the error for the report, the caller decides on a placeholder.
+    pub fn check<T>(&mut self, result: Result<T, AssetError>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.push(error);
+                None
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Magenta and black squares, hard to miss in the game.
+pub fn checkerboard_image() -> Image {
+    const SIZE: u16 = 8;
+    let mut image = Image::gen_image_color(SIZE, SIZE, BLACK);
+    for x in 0..SIZE as u32 {
+        for y in 0..SIZE as u32 {
+            if (x + y) % 2 == 0 {
+                image.set_pixel(x, y, MAGENTA);
+            }
+        }
+    }
+    image
+}
+
+/// Used instead of textures that failed to load.
+pub fn checkerboard_texture() -> Texture2D {
+    let texture = Texture2D::from_image(&checkerboard_image());
+    texture.set_filter(FilterMode::Nearest);
+    texture
+}
+
+/// Single colored image, for UI backgrounds that failed to load.
+pub fn plain_image(color: Color) -> Image {
+    Image::gen_image_color(4, 4, color)
+}
+
+/// The audio decoder panics on data it does not understand, so check the header first.
+pub fn is_sound_data(data: &[u8]) -> bool {
+    let wav = data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WAVE";
+    let ogg = data.starts_with(b"OggS");
+    let flac = data.starts_with(b"fLaC");
+    wav || ogg || flac
+}
+
+/// Header of a 16 bit PCM WAV file, the `data_size` bytes of samples follow it.
+pub fn wav_header(channels: u16, sample_rate: u32, data_size: u32) -> Vec<u8> {
+    let frame_size = channels * 2;
+    let mut wav = Vec::with_capacity(44 + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes()); // size of the format chunk
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&channels.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * frame_size as u32).to_le_bytes()); // bytes per second
+    wav.extend_from_slice(&frame_size.to_le_bytes()); // bytes per frame
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    wav
+}
+
+/// A short 16 bit mono WAV file containing silence.
+pub fn silent_wav() -> Vec<u8> {
+    const SAMPLES: u32 = 64;
+    let data_size = SAMPLES * 2;
+    let mut wav = wav_header(1, 44100, data_size);
+    wav.resize(44 + data_size as usize, 0);
+    wav
+}
+
+/// Used instead of sounds that failed to load.
+pub async fn silent_sound() -> Sound {
+    load_sound_from_bytes(&silent_wav())
+        .await
+        .expect("failed to create silent sound")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkerboard() {
+        let image = checkerboard_image();
+        assert_eq!(image.get_pixel(0, 0), MAGENTA);
+        assert_eq!(image.get_pixel(1, 0), BLACK);
+        assert_eq!(image.get_pixel(1, 1), MAGENTA);
+    }
+
+    #[test]
+    fn test_sound_data() {
+        assert!(is_sound_data(&silent_wav()));
+        assert!(is_sound_data(b"OggS and more"));
+        assert!(!is_sound_data(b"<html>not found</html>"));
+        assert!(!is_sound_data(b""));
+    }
+
+    #[test]
+    fn test_silent_wav_header() {
+        let wav = silent_wav();
+        let riff_size = u32::from_le_bytes(wav[4..8].try_into().unwrap());
+        assert_eq!(riff_size as usize, wav.len() - 8);
+        let data_size = u32::from_le_bytes(wav[40..44].try_into().unwrap());
+        assert_eq!(data_size as usize, wav.len() - 44);
+    }
+
+    #[test]
+    fn test_sibling() {
+        assert_eq!(
+            sibling("assets/apfel/apfel.obj", "materials.mtl"),
+            "assets/apfel/materials.mtl"
+        );
+        assert_eq!(sibling("model.obj", "textures\\a.png"), "textures/a.png");
+    }
+
+    #[test]
+    fn test_override_dir() {
+        let dir = std::env::temp_dir().join(format!("shnek-assets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("yoster.ttf"), b"modded").unwrap();
+        let assets = Assets::new(Some(dir.clone()));
+        assert_eq!(&*assets.read("assets/yoster.ttf").unwrap(), b"modded");
+        // Not overridden, read from the default location
+        assert!(assets.read("assets/poop/poop.obj").is_ok());
+        assert!(matches!(
+            assets.read("assets/missing.png"),
+            Err(AssetError::File { .. })
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "embed-assets")]
+    #[test]
+    fn test_embedded() {
+        assert!(Assets::embedded("assets/apfel/apfel.obj").is_some());
+        assert!(Assets::embedded("assets/apfel/texture.png").is_some());
+        assert!(Assets::embedded("assets/scores.txt").is_none());
+    }
+
+    #[test]
+    fn test_report() {
+        let mut report = LoadReport::new();
+        assert_eq!(report.check(Ok::<_, AssetError>(3)), Some(3));
+        assert!(report.is_empty());
+        let error = AssetError::NoTexture {
+            path: "assets/poop/poop.obj".to_string(),
+            material: "none".to_string(),
+        };
+        assert_eq!(report.check::<i32>(Err(error)), None);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(
+            report.failures[0].to_string(),
+            "assets/poop/poop.obj: no texture for material 'none'"
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use crate::assets::wav_header;
+use macroquad::audio::{
+    load_sound_from_bytes, play_sound, set_sound_volume, stop_sound, PlaySoundParams, Sound,
+};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::FRAC_PI_4;
+use std::sync::OnceLock;
+
+/// A sound with the volume it is played at.
+/// `sound` is `None` when there is no audio device.
+pub struct GameSound {
+    pub sound: Option<Sound>,
+    pub volume: f32,
+}
+
+/// quad-snd panics in its audio thread when there is no audio device
+/// (like on CI machines) and complains about every sound sent to it afterwards,
+/// so sounds are not even loaded then. `--no-audio` turns audio off as well.
+pub fn device_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        if std::env::args().any(|arg| arg == "--no-audio") {
+            return false;
+        }
+        if cfg!(target_os = "linux") {
+            return std::fs::read_to_string("/proc/asound/cards")
+                .is_ok_and(|cards| !cards.contains("no soundcards"));
+        }
+        true
+    })
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Bus {
+    Music,
+    Sfx,
+}
+
+/// Volumes from 0 to 1, saved in the settings.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub music: f32,
+    pub sfx: f32,
+    pub muted: bool,
+    /// Sounds coming from the nearest food, see `FoodHint`.
+    pub food_hint: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            music: 0.5,
+            sfx: 1.0,
+            muted: false,
+            food_hint: true,
+        }
+    }
+}
+
+impl AudioSettings {
+    pub fn volume(&self, bus: Bus) -> f32 {
+        if self.muted {
+            return 0.0;
+        }
+        let bus_volume = match bus {
+            Bus::Music => self.music,
+            Bus::Sfx => self.sfx,
+        };
+        (self.master * bus_volume).clamp(0.0, 1.0)
+    }
+}
+
+/// A looping music track fading in or out.
+struct Track {
+    id: String,
+    sound: Sound,
+    volume: f32,
+    /// 0 is silent, 1 is full volume.
+    fade: f32,
+}
+
+/** Plays sound effects and music, mixed on a music and an effects bus
+below the master volume. Music loops and crossfades when another track
+is started, it is turned down (ducked) while the game is paused.
+*/
+pub struct Audio {
+    pub settings: AudioSettings,
+    music: Option<Track>,
+    fading_out: Vec<Track>,
+    ducked: bool,
+    /// Multiplied with the music volume, moves towards `DUCK_VOLUME` while ducked.
+    duck: f32,
+}
+
+impl Audio {
+    const FADE_TIME: f32 = 1.5; // seconds for a crossfade
+    const DUCK_TIME: f32 = 0.3;
+    const DUCK_VOLUME: f32 = 0.3;
+
+    pub fn new(settings: AudioSettings) -> Self {
+        Self {
+            settings,
+            music: None,
+            fading_out: Vec::new(),
+            ducked: false,
+            duck: 1.0,
+        }
+    }
+
+    /// Plays a sound effect once.
+    pub fn play(&self, sound: &GameSound) {
+        if let Some(data) = &sound.sound {
+            play_sound(
+                data,
+                PlaySoundParams {
+                    looped: false,
+                    volume: sound.volume * self.settings.volume(Bus::Sfx),
+                },
+            );
+        }
+    }
+
+    /// Crossfades to the music track `id`, nothing changes if it is already playing.
+    pub fn play_music(&mut self, id: &str, sound: &GameSound) {
+        if self.music.as_ref().is_some_and(|track| track.id == id) {
+            return;
+        }
+        if let Some(old) = self.music.take() {
+            self.fading_out.push(old);
+        }
+        // Coming back before the track faded out, it continues from where it is
+        if let Some(i) = self.fading_out.iter().position(|track| track.id == id) {
+            self.music = Some(self.fading_out.swap_remove(i));
+            return;
+        }
+        if let Some(data) = &sound.sound {
+            play_sound(
+                data,
+                PlaySoundParams {
+                    looped: true,
+                    volume: 0.0,
+                },
+            );
+            self.music = Some(Track {
+                id: id.to_string(),
+                sound: data.clone(),
+                volume: sound.volume,
+                fade: 0.0,
+            });
+        }
+    }
+
+    /// Turns the music down, for example while the game is paused.
+    pub fn set_ducked(&mut self, ducked: bool) {
+        self.ducked = ducked;
+    }
+
+    /// Moves fades along, has to be called every frame.
+    pub fn update(&mut self, dt: f32) {
+        let duck_target = if self.ducked { Self::DUCK_VOLUME } else { 1.0 };
+        self.duck = approach(self.duck, duck_target, dt / Self::DUCK_TIME);
+        let music_volume = self.settings.volume(Bus::Music) * self.duck;
+
+        let step = dt / Self::FADE_TIME;
+        if let Some(track) = &mut self.music {
+            track.fade = approach(track.fade, 1.0, step);
+            set_sound_volume(&track.sound, track.volume * track.fade * music_volume);
+        }
+        self.fading_out.retain_mut(|track| {
+            track.fade = approach(track.fade, 0.0, step);
+            if track.fade <= 0.0 {
+                stop_sound(&track.sound);
+                return false;
+            }
+            set_sound_volume(&track.sound, track.volume * track.fade * music_volume);
+            true
+        });
+    }
+}
+
+/** A looping sound effect that can be moved between the left and the right speaker.
+macroquad has no panning, so the sound is loaded twice, once with only the left
+channel and once with only the right one, and both play all the time.
+*/
+pub struct PannedLoop {
+    left: Sound,
+    right: Sound,
+}
+
+impl PannedLoop {
+    /// Mono `samples` between -1 and 1, `None` when there is no audio device.
+    pub async fn new(samples: &[f32], sample_rate: u32) -> Option<Self> {
+        if !device_available() {
+            return None;
+        }
+        let left = load_sound_from_bytes(&one_side_wav(samples, sample_rate, false))
+            .await
+            .ok()?;
+        let right = load_sound_from_bytes(&one_side_wav(samples, sample_rate, true))
+            .await
+            .ok()?;
+        for sound in [&left, &right] {
+            play_sound(
+                sound,
+                PlaySoundParams {
+                    looped: true,
+                    volume: 0.0,
+                },
+            );
+        }
+        Some(Self { left, right })
+    }
+
+    /// `pan` goes from -1 (left) to 1 (right), the loudness stays the same while panning.
+    pub fn set(&self, audio: &Audio, volume: f32, pan: f32) {
+        let volume = volume * audio.settings.volume(Bus::Sfx);
+        let angle = (pan.clamp(-1.0, 1.0) + 1.0) * FRAC_PI_4;
+        set_sound_volume(&self.left, volume * angle.cos());
+        set_sound_volume(&self.right, volume * angle.sin());
+    }
+}
+
+/// 16 bit stereo WAV file with `samples` in one channel and silence in the other.
+fn one_side_wav(samples: &[f32], sample_rate: u32, right: bool) -> Vec<u8> {
+    let data_size = samples.len() as u32 * 4;
+    let mut wav = wav_header(2, sample_rate, data_size);
+    for sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        let (left, right) = if right { (0, value) } else { (value, 0) };
+        wav.extend_from_slice(&left.to_le_bytes());
+        wav.extend_from_slice(&right.to_le_bytes());
+    }
+    wav
+}
+
+/// Moves `value` towards `target` by at most `step`.
+fn approach(value: f32, target: f32, step: f32) -> f32 {
+    if value < target {
+        (value + step).min(target)
+    } else {
+        (value - step).max(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bus_volume() {
+        let mut settings = AudioSettings {
+            master: 0.5,
+            music: 0.4,
+            ..Default::default()
+        };
+        assert!((settings.volume(Bus::Music) - 0.2).abs() < 1e-6);
+        assert!((settings.volume(Bus::Sfx) - 0.5).abs() < 1e-6);
+        settings.muted = true;
+        assert_eq!(settings.volume(Bus::Sfx), 0.0);
+    }
+
+    #[test]
+    fn test_one_side_wav() {
+        let wav = one_side_wav(&[1.0, -1.0], 22050, true);
+        assert!(crate::assets::is_sound_data(&wav));
+        assert_eq!(wav.len(), 44 + 8);
+        // Left channel silent, right channel full
+        assert_eq!(&wav[44..48], &[0, 0, 0xff, 0x7f]);
+        assert_eq!(&wav[48..52], &[0, 0, 0x01, 0x80]);
+    }
+
+    #[test]
+    fn test_approach() {
+        assert_eq!(approach(0.0, 1.0, 0.25), 0.25);
+        assert_eq!(approach(0.9, 1.0, 0.25), 1.0);
+        assert_eq!(approach(0.1, 0.0, 0.25), 0.0);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::draw_utils::BACKGROUND_COLOR;
+use crate::events::Events;
+use crate::food::FoodFactory;
+use crate::manifest::AssetLibrary;
+use crate::models3d::{Culling, DrawStats, Lighting};
+use crate::movement::View;
+use crate::orientation::Orientation;
+use crate::snake::Shnek;
+use macroquad::prelude::*;
+use std::time::Instant;
+
+const SEGMENTS: usize = 500;
+const WARMUP_FRAMES: usize = 60;
+const FRAMES: usize = 600;
+
+/** Frame time benchmark with a long snake, run with `cargo run --release -- --benchmark`.
+The snake moves on a fixed path, so results can be compared between versions.
+Times are CPU time spent on updating and drawing, without waiting for vsync.
+*/
+pub async fn run(library: &AssetLibrary, food_factory: &FoodFactory, lighting: &Lighting) {
+    let view = View::new();
+    let mut player = Shnek::new(
+        library.model("snake.head"),
+        library.model("snake.body"),
+        3,
+        view.repeat(lighting.fog.distance()),
+    );
+    let mut orientation = Orientation::from_forward_up(vec3(1., 0., 0.), vec3(0., 0., 1.));
+    player.set_orientation(orientation);
+    while player.get_length() < SEGMENTS {
+        player.add_segment();
+    }
+
+    let dt = 1.0 / 60.0;
+    let mut times: Vec<f32> = Vec::with_capacity(FRAMES);
+    let mut stats = DrawStats::default();
+    // Nothing reacts to the snake moving around
+    let mut events = Events::new();
+    for frame in 0..WARMUP_FRAMES + FRAMES {
+        let start = Instant::now();
+
+        // Turn slowly, so the body curls up and fills the space
+        orientation.rotate(orientation.up(), 0.6 * dt);
+        orientation.rotate(orientation.forward(), 0.2 * dt);
+        player.set_orientation(orientation);
+        player.move_forward(dt, &mut events);
+        events.clear();
+
+        let camera = view.camera(player.get_camera_position());
+        set_camera(&camera);
+        clear_background(BACKGROUND_COLOR);
+        let culling = Culling::from_camera(&camera, view.view_distance(lighting.fog.distance()));
+        lighting.apply(camera.position);
+        stats = food_factory.draw(&culling);
+        stats += player.draw(&culling);
+        lighting.reset();
+
+        set_default_camera();
+        draw_text(
+            format!(
+                "benchmark: frame {} / {}",
+                frame + 1,
+                WARMUP_FRAMES + FRAMES
+            ),
+            10.0,
+            30.0,
+            30.0,
+            BLACK,
+        );
+
+        if frame >= WARMUP_FRAMES {
+            times.push(start.elapsed().as_secs_f32() * 1000.0);
+        }
+        next_frame().await;
+    }
+
+    times.sort_by(|a, b| a.total_cmp(b));
+    let average = times.iter().sum::<f32>() / times.len() as f32;
+    let percentile = |p: f32| times[((times.len() - 1) as f32 * p) as usize];
+    println!("{} segments, {} frames", SEGMENTS, FRAMES);
+    println!(
+        "frame time (ms): average {:.2}, median {:.2}, 95th percentile {:.2}, max {:.2}",
+        average,
+        percentile(0.5),
+        percentile(0.95),
+        percentile(1.0)
+    );
+    println!(
+        "last frame: {} meshes drawn, {} vertices, {} culled",
+        stats.meshes_drawn, stats.vertices, stats.culled
+    );
+}
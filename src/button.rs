@@ -1,4 +1,5 @@
-use macroquad::audio::load_sound;
+use crate::assets::{is_sound_data, AssetError, Assets};
+use macroquad::audio::load_sound_from_bytes;
 use macroquad::{
     audio::Sound,
     prelude::*,
@@ -37,9 +38,11 @@ impl MenuButtons {
     // }
 }
 
-pub async fn load_window_background(path: &str) -> Image {
+pub async fn load_ui_image(assets: &Assets, path: &str) -> Result<Image, AssetError> {
     // load_image("assets/Solid_black.png").await.unwrap();
-    load_image(path).await.unwrap()
+    let data = assets.read(path)?;
+    Image::from_file_with_format(&data, None)
+        .map_err(|error| AssetError::from_macroquad(path, error))
 }
 
 // pub async fn button_background() -> Image {
@@ -50,8 +53,14 @@ pub async fn load_window_background(path: &str) -> Image {
 //     load_image("assets/pressed_button.png").await.unwrap();
 // }
 
-pub async fn load_font(font_path: &str) -> Vec<u8> {
-    load_file(font_path).await.unwrap()
+/// Reads a TTF font and checks that it can be used, so the styles don't have to.
+pub async fn load_font(assets: &Assets, font_path: &str) -> Result<Vec<u8>, AssetError> {
+    let font = assets.read(font_path)?.into_owned();
+    load_ttf_font_from_bytes(&font).map_err(|_| AssetError::Font {
+        path: font_path.to_string(),
+        message: "not a valid TTF font".to_string(),
+    })?;
+    Ok(font)
 }
 
 pub async fn load_window_style(window_background: Image) -> Style {
@@ -63,31 +72,28 @@ pub async fn load_window_style(window_background: Image) -> Style {
         .build()
 }
 
-pub async fn load_button_style(font: Vec<u8>) -> Style {
-    root_ui()
+/// Without a font the default macroquad font is used.
+pub async fn load_button_style(font: Option<&[u8]>, font_size: u16) -> Style {
+    let mut builder = root_ui()
         .style_builder()
         // .background(button_background)
         // .background_clicked(button_clicked_background)
         .background_margin(RectOffset::new(16.0, 16.0, 16.0, 16.0))
-        .margin(RectOffset::new(16.0, 0.0, -8.0, -8.0))
-        .font(&font)
-        .unwrap()
-        .text_color(BLACK)
-        .font_size(64)
-        .build()
+        .margin(RectOffset::new(16.0, 0.0, -8.0, -8.0));
+    if let Some(font) = font {
+        builder = builder.font(font).expect("font was checked in load_font");
+    }
+    builder.text_color(BLACK).font_size(font_size).build()
 }
 
-pub async fn load_label_style(font: Vec<u8>) -> Style {
-    root_ui()
-        .style_builder()
-        .font(&font)
-        .unwrap()
-        .text_color(WHITE)
-        .font_size(28)
-        .build()
+pub async fn load_label_style(font: Option<&[u8]>, font_size: u16) -> Style {
+    let mut builder = root_ui().style_builder();
+    if let Some(font) = font {
+        builder = builder.font(font).expect("font was checked in load_font");
+    }
+    builder.text_color(WHITE).font_size(font_size).build()
 }
 
-#[allow(dead_code)]
 pub async fn load_ui_skin(window_style: Style, button_style: Style, label_style: Style) -> Skin {
     Skin {
         window_style,
@@ -97,8 +103,17 @@ pub async fn load_ui_skin(window_style: Style, button_style: Style, label_style:
     }
 }
 
-pub async fn loading_sound(sound_path: &str) -> Sound {
-    load_sound(sound_path).await.unwrap()
+pub async fn loading_sound(assets: &Assets, sound_path: &str) -> Result<Sound, AssetError> {
+    let data = assets.read(sound_path)?;
+    if !is_sound_data(&data) {
+        return Err(AssetError::Sound {
+            path: sound_path.to_string(),
+            message: "not a WAV, OGG or FLAC file".to_string(),
+        });
+    }
+    load_sound_from_bytes(&data)
+        .await
+        .map_err(|error| AssetError::from_macroquad(sound_path, error))
 }
 
 // pub async fn click_sound() {
@@ -1,6 +1,7 @@
 use macroquad::prelude::*;
 
 pub const SPACE_SIZE: f32 = 60.0;
+pub const BACKGROUND_COLOR: Color = Color::new(0.68, 0.85, 0.90, 1.0);
 
 /** This is here for development / debug purposes only.
 */
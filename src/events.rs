@@ -0,0 +1,76 @@
+use crate::food::FoodVariant;
+
+/// Why the snake died.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DeathCause {
+    /// The head hit the body.
+    TailCollision,
+    /// Boosted for too long without segments left to burn.
+    BoostStarvation,
+    /// Bad food shrank the snake below its start length.
+    BadFood,
+}
+
+impl DeathCause {
+    /// Shown on the game over screen.
+    pub fn description(&self) -> &'static str {
+        match self {
+            DeathCause::TailCollision => "Bit your own tail",
+            DeathCause::BoostStarvation => "Boosted with nothing left",
+            DeathCause::BadFood => "Shrank from bad food",
+        }
+    }
+}
+
+/// Something that happened in the game simulation.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GameEvent {
+    FoodEaten {
+        variant: FoodVariant,
+        quality: u32,
+    },
+    /// Bad food that was not eaten in time disappeared.
+    FoodExpired {
+        variant: FoodVariant,
+    },
+    /// A segment was taken by bad food.
+    SegmentLost,
+    /// Boosting turned the last segment into poop.
+    Pooped,
+    BoostStarted,
+    BoostEnded,
+    /// The head left the space on one side and came back on the other, axis 0 is X.
+    Wrapped {
+        axis: usize,
+    },
+    Died {
+        cause: DeathCause,
+    },
+}
+
+/** Gameplay events of the current frame. The simulation pushes them
+while it updates, everything reacting to them (sounds, statistics, ...)
+reads them afterwards, the queue is cleared at the start of every frame.
+*/
+#[derive(Debug, Default)]
+pub struct Events {
+    events: Vec<GameEvent>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, event: GameEvent) {
+        self.events.push(event);
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &GameEvent> {
+        self.events.iter()
+    }
+}
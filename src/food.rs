@@ -1,8 +1,12 @@
 use crate::draw_utils::SPACE_SIZE;
-use crate::models3d::{Model3D, MultiModel};
+use crate::events::{Events, GameEvent};
+use crate::manifest::AssetLibrary;
+use crate::models3d::{Culling, DrawStats, Model3D, MultiModel};
+use crate::orientation::Orientation;
 use crate::snake::*;
 use macroquad::prelude::*;
 use macroquad::rand::*;
+use std::rc::Rc;
 
 pub fn random_vec3(min: f32, max: f32) -> Vec3 {
     vec3(
@@ -12,122 +16,168 @@ pub fn random_vec3(min: f32, max: f32) -> Vec3 {
     )
 }
 
-#[derive(PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub enum FoodVariant {
     Normal,
     Bad,
     Poop,
 }
 
+impl FoodVariant {
+    pub const ALL: [FoodVariant; 3] = [FoodVariant::Normal, FoodVariant::Bad, FoodVariant::Poop];
+
+    /// Shown to the player.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FoodVariant::Normal => "food",
+            FoodVariant::Bad => "bad food",
+            FoodVariant::Poop => "poop",
+        }
+    }
+
+    /// Asset id of the models, see `AssetLibrary::model_variants`.
+    pub fn model_id(&self) -> &'static str {
+        match self {
+            FoodVariant::Normal => "food.normal",
+            FoodVariant::Bad => "food.bad",
+            FoodVariant::Poop => "food.poop",
+        }
+    }
+}
+
+/// A piece of food as seen from the head of the snake.
+#[derive(Copy, Clone, Debug)]
+pub struct FoodTarget {
+    pub id: usize,
+    pub variant: FoodVariant,
+    /// Shortest wrapped vector from the head to the food.
+    pub delta: Vec3,
+    /// Seconds until the food expires, only bad food expires.
+    pub time_left: Option<f32>,
+}
+
+impl FoodTarget {
+    pub fn distance(&self) -> f32 {
+        self.delta.length()
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Food {
     pub time_created: f32,
     pub position: Vec3,
-    pub up: Vec3,
-    pub front: Vec3,
+    pub orientation: Orientation,
     pub size: f32,
     pub quality: u32,
     id: usize,
+    /// Which of the models of the variant is used.
+    look: usize,
 }
 
-pub struct FoodFactory<'a> {
+pub struct FoodFactory {
     quality_range: (u32, u32),
     good_food: Vec<Food>,
-    good_food_model: MultiModel<'a>,
+    good_food_models: Vec<MultiModel>,
     bad_food: Vec<Food>,
-    bad_food_model: MultiModel<'a>,
+    bad_food_models: Vec<MultiModel>,
     poop: Vec<Food>,
-    poop_model: MultiModel<'a>,
+    poop_models: Vec<MultiModel>,
     pub max_food: u32,
     id_counter: usize,
 }
 
-impl<'a> FoodFactory<'a> {
+impl FoodFactory {
     const FOOD_COLLISION_DISTANCE: f32 = 10.0;
     const BAD_FOOD_LIFETIME: f32 = 30.0; // seconds (or halved if boost moving)
 
-    pub fn new(
-        base_good_food_model: &'a Model3D,
-        base_bad_food_model: &'a Model3D,
-        base_poop_model: &'a Model3D,
-    ) -> Self {
+    /// Every model of a variant in the asset manifest is used, picked at random for each food.
+    pub fn new(library: &AssetLibrary, repeat: i32) -> Self {
+        Self::with_models(|variant| {
+            library
+                .model_variants(variant.model_id())
+                .into_iter()
+                .map(|model| MultiModel::new(model, repeat))
+                .collect()
+        })
+    }
+
+    /// `models` gives the looks of every variant, at least one each.
+    pub fn with_models(models: impl Fn(FoodVariant) -> Vec<MultiModel>) -> Self {
         let mut s = Self {
             quality_range: (1, 2),
             good_food: Vec::new(),
             bad_food: Vec::new(),
             poop: Vec::new(),
             max_food: 1,
-            good_food_model: MultiModel::new(base_good_food_model, 3),
-            bad_food_model: MultiModel::new(base_bad_food_model, 3),
-            poop_model: MultiModel::new(base_poop_model, 3),
+            good_food_models: models(FoodVariant::Normal),
+            bad_food_models: models(FoodVariant::Bad),
+            poop_models: models(FoodVariant::Poop),
             id_counter: 0,
         };
-        let front = vec3(0., 1., 0.);
-        let up = vec3(0., 0., 1.);
         s.new_custom(
             vec3(10., 10., 10.),
             1.,
             1,
             FoodVariant::Normal,
-            front,
-            up,
+            Orientation::IDENTITY,
             0.0,
         );
         s
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new_custom(
         &mut self,
         position: Vec3,
         size: f32,
         quality: u32,
         variant: FoodVariant,
-        front: Vec3,
-        up: Vec3,
+        orientation: Orientation,
         snake_time: f32,
     ) {
-        let food = Food::new_custom(
-            position,
-            up,
-            front,
-            size,
-            quality,
-            self.id_counter,
-            snake_time,
-        );
-        let food_translation = Mat4::from_translation(food.position);
-        let right = food.front.cross(food.up).normalize();
-        let food_rotation = Mat3::from_cols(right, food.front, food.up);
+        let id = self.id_counter;
+        let models = self.models_mut(variant);
+        let look = gen_range(0, models.len());
+        let food = Food::new_custom(position, orientation, size, quality, id, look, snake_time);
         let scale = food.size * (food.quality as f32).powf(1. / 3.);
-        let food_matrix = food_translation.mul_mat4(&Mat4::from_mat3(scale * food_rotation));
+        let food_matrix = food
+            .orientation
+            .to_mat4(food.position)
+            .mul_mat4(&Mat4::from_scale(Vec3::splat(scale)));
 
         // Update models
+        models[look].add_transformed(&food_matrix, id);
+        self.foods_mut(variant).push(food);
+        self.id_counter += 1;
+    }
+
+    fn foods_mut(&mut self, variant: FoodVariant) -> &mut Vec<Food> {
         match variant {
-            FoodVariant::Normal => {
-                self.good_food_model
-                    .add_transformed(&food_matrix, self.id_counter);
-                self.good_food.push(food);
-            }
-            FoodVariant::Poop => {
-                self.poop_model
-                    .add_transformed(&food_matrix, self.id_counter);
-                self.poop.push(food);
-            }
-            FoodVariant::Bad => {
-                self.bad_food_model
-                    .add_transformed(&food_matrix, self.id_counter);
-                self.bad_food.push(food);
-            }
+            FoodVariant::Normal => &mut self.good_food,
+            FoodVariant::Bad => &mut self.bad_food,
+            FoodVariant::Poop => &mut self.poop,
+        }
+    }
+
+    fn models_mut(&mut self, variant: FoodVariant) -> &mut Vec<MultiModel> {
+        match variant {
+            FoodVariant::Normal => &mut self.good_food_models,
+            FoodVariant::Bad => &mut self.bad_food_models,
+            FoodVariant::Poop => &mut self.poop_models,
         }
-        self.id_counter += 1;
     }
 
     pub fn new_random(&mut self, max_pos: f32, food_variant: FoodVariant, snake_time: f32) {
         let position = random_vec3(0., max_pos);
         let quality = gen_range(self.quality_range.0, self.quality_range.1);
-        let front = vec3(0., 1., 0.);
-        let up = vec3(0., 0., 1.);
-        self.new_custom(position, 1., quality, food_variant, front, up, snake_time);
+        self.new_custom(
+            position,
+            1.,
+            quality,
+            food_variant,
+            Orientation::IDENTITY,
+            snake_time,
+        );
     }
 
     pub fn new_random_with_quality(
@@ -138,36 +188,85 @@ impl<'a> FoodFactory<'a> {
         snake_time: f32,
     ) {
         let position = random_vec3(0., max_pos);
-        let front = vec3(0., 1., 0.);
-        let up = vec3(0., 0., 1.);
-        self.new_custom(position, 1., quality, food_variant, front, up, snake_time);
+        self.new_custom(
+            position,
+            1.,
+            quality,
+            food_variant,
+            Orientation::IDENTITY,
+            snake_time,
+        );
     }
 
     pub fn remove_food_model(&mut self, i: usize, variant: FoodVariant) {
-        match variant {
-            FoodVariant::Normal => {
-                self.good_food_model
-                    .remove_transformed(self.good_food[i].id);
-                self.good_food_model.refresh_transformed();
-                self.good_food.remove(i);
-            }
-            FoodVariant::Bad => {
-                self.bad_food_model.remove_transformed(self.bad_food[i].id);
-                self.bad_food_model.refresh_transformed();
-                self.bad_food.remove(i);
-            }
-            FoodVariant::Poop => {
-                self.poop_model.remove_transformed(self.poop[i].id);
-                self.poop_model.refresh_transformed();
-                self.poop.remove(i);
-            }
-        }
+        let food = self.foods_mut(variant).remove(i);
+        self.models_mut(variant)[food.look].remove_transformed(food.id);
     }
 
     pub fn food_count(&self) -> usize {
         self.good_food.len()
     }
 
+    /// Ids and positions (within the base cube) of all food, poop included.
+    pub fn food_positions(&self) -> impl Iterator<Item = (usize, Vec3, FoodVariant)> + '_ {
+        let good = self
+            .good_food
+            .iter()
+            .map(|f| (f.id, f.get_position(), FoodVariant::Normal));
+        let bad = self
+            .bad_food
+            .iter()
+            .map(|f| (f.id, f.get_position(), FoodVariant::Bad));
+        let poop = self
+            .poop
+            .iter()
+            .map(|f| (f.id, f.get_position(), FoodVariant::Poop));
+        good.chain(bad).chain(poop)
+    }
+
+    /// The good food with the shortest wrapped path from `position`.
+    pub fn nearest_food(&self, position: Vec3) -> Option<FoodTarget> {
+        Self::nearest(&self.good_food, FoodVariant::Normal, position)
+    }
+
+    /// The bad food with the shortest wrapped path from `position`.
+    pub fn nearest_bad_food(&self, position: Vec3) -> Option<FoodTarget> {
+        Self::nearest(&self.bad_food, FoodVariant::Bad, position)
+    }
+
+    fn nearest(foods: &[Food], variant: FoodVariant, position: Vec3) -> Option<FoodTarget> {
+        foods
+            .iter()
+            .map(|food| FoodTarget {
+                id: food.id,
+                variant,
+                delta: mod_delta(position, food.get_position()),
+                time_left: None,
+            })
+            .min_by(|a, b| a.distance().total_cmp(&b.distance()))
+    }
+
+    /// Bad food that will expire in less than `within` seconds.
+    pub fn expiring_bad_food(
+        &self,
+        position: Vec3,
+        snake_time: f32,
+        within: f32,
+    ) -> Vec<FoodTarget> {
+        self.bad_food
+            .iter()
+            .filter_map(|food| {
+                let time_left = Self::BAD_FOOD_LIFETIME - (snake_time - food.time_created);
+                (time_left < within).then(|| FoodTarget {
+                    id: food.id,
+                    variant: FoodVariant::Bad,
+                    delta: mod_delta(position, food.get_position()),
+                    time_left: Some(time_left.max(0.0)),
+                })
+            })
+            .collect()
+    }
+
     fn generate_food(&mut self, snake: &Shnek, remove_count: usize) {
         let score = snake.get_score();
         // make new good food
@@ -191,19 +290,17 @@ impl<'a> FoodFactory<'a> {
         }
     }
 
-    fn check_good_food_collision(&mut self, snake: &mut Shnek) -> (f32, bool) {
-        let mut min_dist = SPACE_SIZE * 3.0;
-        let mut eaten = false;
+    fn check_good_food_collision(&mut self, snake: &mut Shnek, events: &mut Events) {
         let mut remove: Vec<usize> = Vec::new();
         for i in 0..self.good_food.len() {
             let food = &self.good_food[i];
             let dist = mod_distance(snake.get_position(), food.get_position());
-            if dist < min_dist {
-                min_dist = dist;
-            }
             // We are eating
             if dist < Self::FOOD_COLLISION_DISTANCE {
-                eaten = true;
+                events.push(GameEvent::FoodEaten {
+                    variant: FoodVariant::Normal,
+                    quality: food.quality,
+                });
                 for _ in 0..food.quality {
                     snake.add_segment();
                 }
@@ -214,29 +311,29 @@ impl<'a> FoodFactory<'a> {
         for i in remove.iter().rev() {
             self.remove_food_model(*i, FoodVariant::Normal);
         }
-        (min_dist, eaten)
     }
 
-    fn check_bad_food_collision(&mut self, snake: &mut Shnek) -> (f32, bool) {
-        let mut min_dist = SPACE_SIZE * 3.0;
-        let mut eaten = false;
+    fn check_bad_food_collision(&mut self, snake: &mut Shnek, events: &mut Events) {
         let mut remove: Vec<usize> = Vec::new();
         for i in 0..self.bad_food.len() {
             let food = &self.bad_food[i];
             // Bad food expires after some time
             if snake.time_moving - food.time_created > Self::BAD_FOOD_LIFETIME {
+                events.push(GameEvent::FoodExpired {
+                    variant: FoodVariant::Bad,
+                });
                 remove.push(i);
                 continue;
             }
             let dist = mod_distance(snake.get_position(), food.get_position());
-            if dist < min_dist {
-                min_dist = dist;
-            }
             // We are eating, do not generate new food
             if dist < Self::FOOD_COLLISION_DISTANCE {
-                eaten = true;
+                events.push(GameEvent::FoodEaten {
+                    variant: FoodVariant::Bad,
+                    quality: food.quality,
+                });
                 for _ in 0..food.quality {
-                    snake.pop_segment();
+                    snake.pop_segment(events);
                 }
                 remove.push(i);
             }
@@ -244,22 +341,19 @@ impl<'a> FoodFactory<'a> {
         for i in remove.iter().rev() {
             self.remove_food_model(*i, FoodVariant::Bad);
         }
-        (min_dist, eaten)
     }
 
-    fn check_poop_collision(&mut self, snake: &mut Shnek) -> (f32, bool) {
-        let mut min_dist = SPACE_SIZE * 3.0;
-        let mut eaten = false;
+    fn check_poop_collision(&mut self, snake: &mut Shnek, events: &mut Events) {
         let mut remove: Vec<usize> = Vec::new();
         for i in 0..self.poop.len() {
             let food = &self.poop[i];
             let dist = mod_distance(snake.get_position(), food.get_position());
-            if dist < min_dist {
-                min_dist = dist;
-            }
             // We are eating, do not generate new food
             if dist < Self::FOOD_COLLISION_DISTANCE {
-                eaten = true;
+                events.push(GameEvent::FoodEaten {
+                    variant: FoodVariant::Poop,
+                    quality: food.quality,
+                });
                 for _ in 0..food.quality {
                     snake.add_segment();
                 }
@@ -269,40 +363,45 @@ impl<'a> FoodFactory<'a> {
         for i in remove.iter().rev() {
             self.remove_food_model(*i, FoodVariant::Poop);
         }
-        (min_dist, eaten)
     }
 
-    pub fn check_food_collision(&mut self, snake: &mut Shnek) -> (f32, bool) {
+    /// Eats the food the snake is touching, see `GameEvent::FoodEaten`.
+    /// Returns the nearest good food after eating.
+    pub fn check_food_collision(
+        &mut self,
+        snake: &mut Shnek,
+        events: &mut Events,
+    ) -> Option<FoodTarget> {
         let score = snake.get_score();
 
-        let (md1, eaten1) = self.check_good_food_collision(snake);
-        let (md2, eaten2) = self.check_bad_food_collision(snake);
-        let (md3, eaten3) = self.check_poop_collision(snake);
+        self.check_good_food_collision(snake, events);
+        self.check_bad_food_collision(snake, events);
+        self.check_poop_collision(snake, events);
 
         self.quality_range.1 = (((score + 1) as f64).log10()).round() as u32 + 1;
         self.max_food = ((score as f64 * 2.).log10()).round() as u32 + 1;
 
-        (md1.min(md2.min(md3)), eaten1 || eaten2 || eaten3)
+        self.nearest_food(snake.get_position())
     }
 }
 
 impl Food {
     fn new_custom(
         position: Vec3,
-        up: Vec3,
-        front: Vec3,
+        orientation: Orientation,
         size: f32,
         quality: u32,
         id: usize,
+        look: usize,
         time_created: f32,
     ) -> Self {
         Self {
             position,
-            up,
-            front,
+            orientation,
             size,
             quality,
             id,
+            look,
             time_created,
         }
     }
@@ -312,10 +411,38 @@ impl Food {
     }
 }
 
-impl FoodFactory<'_> {
-    pub fn draw(&self) {
-        self.good_food_model.draw();
-        self.bad_food_model.draw();
-        self.poop_model.draw();
+impl FoodFactory {
+    pub fn draw(&self, culling: &Culling) -> DrawStats {
+        let mut stats = DrawStats::default();
+        let models = self
+            .good_food_models
+            .iter()
+            .chain(&self.bad_food_models)
+            .chain(&self.poop_models);
+        for model in models {
+            stats += model.draw(culling);
+        }
+        stats
+    }
+
+    /// Switches to a reloaded model, see `MultiModel::replace_base_model`.
+    pub fn replace_model(&mut self, old: &Rc<Model3D>, new: &Rc<Model3D>) {
+        for model in self.models() {
+            model.replace_base_model(old, new);
+        }
+    }
+
+    /// Copies of the world drawn in every direction, when the view distance changed.
+    pub fn set_repeat(&mut self, repeat: i32) {
+        for model in self.models() {
+            model.set_repeat(repeat);
+        }
+    }
+
+    fn models(&mut self) -> impl Iterator<Item = &mut MultiModel> {
+        self.good_food_models
+            .iter_mut()
+            .chain(&mut self.bad_food_models)
+            .chain(&mut self.poop_models)
     }
 }
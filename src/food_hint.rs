@@ -0,0 +1,166 @@
+use crate::audio::{Audio, PannedLoop};
+use crate::draw_utils::SPACE_SIZE;
+use crate::food::{FoodFactory, FoodTarget};
+use crate::movement::View;
+use crate::snake::Shnek;
+use std::f32::consts::TAU;
+
+/** Sounds coming from the direction of the nearest food, for finding it without
+looking around. Good food chimes softly and can always be heard a little, bad food
+hums and only gets audible when it is close. Left and right come from the view,
+food behind the snake is quieter than food in front of it.
+*/
+pub struct FoodHint {
+    good: Option<PannedLoop>,
+    bad: Option<PannedLoop>,
+}
+
+impl FoodHint {
+    const SAMPLE_RATE: u32 = 44100;
+    const GOOD_VOLUME: f32 = 0.4;
+    const BAD_VOLUME: f32 = 0.3;
+    /// Nothing in the wrapped space is further away than this.
+    const MAX_DISTANCE: f32 = SPACE_SIZE * 0.87;
+
+    pub async fn new() -> Self {
+        Self {
+            good: PannedLoop::new(&chime(Self::SAMPLE_RATE), Self::SAMPLE_RATE).await,
+            bad: PannedLoop::new(&hum(Self::SAMPLE_RATE), Self::SAMPLE_RATE).await,
+        }
+    }
+
+    /// Moves the sounds to the nearest food, they are silent while `playing` is false.
+    pub fn update(
+        &self,
+        audio: &Audio,
+        view: &View,
+        player: &Shnek,
+        food_factory: &FoodFactory,
+        playing: bool,
+    ) {
+        let enabled = playing && audio.settings.food_hint;
+        let position = player.get_position();
+        let cues = [
+            (
+                &self.good,
+                food_factory.nearest_food(position),
+                Self::GOOD_VOLUME,
+                0.1,
+            ),
+            (
+                &self.bad,
+                food_factory.nearest_bad_food(position),
+                Self::BAD_VOLUME,
+                0.0,
+            ),
+        ];
+        for (cue, target, volume, floor) in cues {
+            let Some(cue) = cue else {
+                continue;
+            };
+            match target.filter(|_| enabled) {
+                Some(target) => {
+                    let (loudness, pan) = placement(&target, view, floor);
+                    cue.set(audio, volume * loudness, pan);
+                }
+                None => cue.set(audio, 0.0, 0.0),
+            }
+        }
+    }
+}
+
+/// Loudness from 0 to 1 and pan from -1 (left) to 1 (right) of food seen from the view.
+/// `floor` is the loudness of food that is as far away as possible.
+fn placement(target: &FoodTarget, view: &View, floor: f32) -> (f32, f32) {
+    let right = view.right().dot(target.delta);
+    let forward = view.forward().dot(target.delta);
+    place(target.distance(), right, forward, floor)
+}
+
+/// `right` and `forward` are the parts of the way to the food along these directions.
+fn place(distance: f32, right: f32, forward: f32, floor: f32) -> (f32, f32) {
+    let closeness = (1.0 - distance / FoodHint::MAX_DISTANCE).clamp(0.0, 1.0);
+    let mut loudness = floor + (1.0 - floor) * closeness * closeness;
+    if forward < 0.0 {
+        loudness *= 0.6;
+    }
+    // Straight above or below the head is in the middle
+    let horizontal = (right * right + forward * forward).sqrt();
+    let pan = if horizontal > 1e-3 {
+        right / horizontal
+    } else {
+        0.0
+    };
+    (loudness, pan)
+}
+
+/// Half a second loop: a soft high ping.
+fn chime(sample_rate: u32) -> Vec<f32> {
+    let length = sample_rate as usize / 2;
+    (0..length)
+        .map(|i| {
+            let t = i as f32 / sample_rate as f32;
+            let envelope = (t / 0.005).min(1.0) * (-t * 12.0).exp();
+            (TAU * 880.0 * t).sin() * envelope * 0.8
+        })
+        .collect()
+}
+
+/// 0.8 second loop: two low buzzing pulses.
+fn hum(sample_rate: u32) -> Vec<f32> {
+    let length = sample_rate as usize * 4 / 5;
+    (0..length)
+        .map(|i| {
+            let t = i as f32 / sample_rate as f32;
+            let pulse = (t % 0.2) / 0.2;
+            let envelope = if t < 0.4 {
+                (pulse * std::f32::consts::PI).sin()
+            } else {
+                0.0
+            };
+            // A few harmonics of a low note, sounds rough compared to the chime
+            let wave = (1..=4)
+                .map(|k| (TAU * 110.0 * k as f32 * t).sin() / k as f32)
+                .sum::<f32>();
+            wave * envelope * 0.4
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_place() {
+        // Right in front, close
+        let (loudness, pan) = place(5.0, 0.0, 5.0, 0.1);
+        assert!(loudness > 0.8);
+        assert!(pan.abs() < 1e-6);
+        // To the right
+        let (_, pan) = place(10.0, 10.0, 0.0, 0.1);
+        assert!((pan - 1.0).abs() < 1e-6);
+        // Behind on the left is quieter than in front
+        let (behind, pan) = place(20.0, -10.0, -17.0, 0.1);
+        let (front, _) = place(20.0, -10.0, 17.0, 0.1);
+        assert!(pan < 0.0);
+        assert!(behind < front);
+        // As far as possible, only the floor is left
+        let (loudness, _) = place(FoodHint::MAX_DISTANCE, 0.0, 30.0, 0.1);
+        assert!((loudness - 0.1).abs() < 1e-6);
+        // Straight above
+        let (_, pan) = place(10.0, 0.0, 0.0, 0.1);
+        assert_eq!(pan, 0.0);
+    }
+
+    #[test]
+    fn test_loops_end_quietly() {
+        for samples in [chime(44100), hum(44100)] {
+            assert!(samples.iter().all(|sample| sample.abs() <= 1.0));
+            assert!(
+                samples.last().unwrap().abs() < 0.01,
+                "would click when looping"
+            );
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/** Watches the asset directory in development mode (`--dev`).
+Modification times are polled a few times per second, there are only a few
+dozen files and this works the same everywhere without a file system
+notification library.
+*/
+pub struct AssetWatcher {
+    root: PathBuf,
+    times: HashMap<PathBuf, SystemTime>,
+    since_check: f32,
+}
+
+impl AssetWatcher {
+    const INTERVAL: f32 = 0.5; // seconds between checks
+
+    /// Watches `root`, which takes the place of the `assets` directory.
+    pub fn new(root: &Path) -> Self {
+        let root = root.to_path_buf();
+        let times = scan(&root);
+        Self {
+            root,
+            times,
+            since_check: 0.0,
+        }
+    }
+
+    /// Asset paths (like `assets/head/snake_head.obj`) of files that were changed,
+    /// added or removed since the last check.
+    pub fn poll(&mut self, dt: f32) -> Vec<String> {
+        self.since_check += dt;
+        if self.since_check < Self::INTERVAL {
+            return Vec::new();
+        }
+        self.since_check = 0.0;
+
+        let times = scan(&self.root);
+        let mut changed: Vec<String> = times
+            .iter()
+            .filter(|(path, time)| self.times.get(*path) != Some(time))
+            .map(|(path, _)| path)
+            .chain(self.times.keys().filter(|path| !times.contains_key(*path)))
+            .filter_map(|path| asset_path(&self.root, path))
+            .collect();
+        changed.sort();
+        self.times = times;
+        changed
+    }
+}
+
+/// Modification times of all files below `dir`.
+fn scan(dir: &Path) -> HashMap<PathBuf, SystemTime> {
+    let mut times = HashMap::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                dirs.push(path);
+            } else if let Ok(time) = metadata.modified() {
+                times.insert(path, time);
+            }
+        }
+    }
+    times
+}
+
+/// `root/head/snake_head.obj` -> `assets/head/snake_head.obj`
+fn asset_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let parts: Vec<&str> = relative
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect::<Option<_>>()?;
+    Some(format!("assets/{}", parts.join("/")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_asset_path() {
+        let root = Path::new("mods/shiny");
+        assert_eq!(
+            asset_path(root, &root.join("head").join("snake_head.obj")),
+            Some("assets/head/snake_head.obj".to_string())
+        );
+        assert_eq!(asset_path(root, Path::new("elsewhere/a.png")), None);
+    }
+
+    #[test]
+    fn test_poll() {
+        let dir = std::env::temp_dir().join(format!("shnek-watch-{}", std::process::id()));
+        fs::create_dir_all(dir.join("head")).unwrap();
+        fs::write(dir.join("head/snake_head.obj"), "v 0 0 0").unwrap();
+        let mut watcher = AssetWatcher::new(&dir);
+        assert!(watcher.poll(1.0).is_empty());
+
+        // Set the time explicitly, file systems can have coarse timestamps
+        let file = fs::File::options()
+            .write(true)
+            .open(dir.join("head/snake_head.obj"))
+            .unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        fs::write(dir.join("new.png"), "").unwrap();
+        // Not checked again before the interval is over
+        assert!(watcher.poll(0.1).is_empty());
+        assert_eq!(
+            watcher.poll(1.0),
+            vec!["assets/head/snake_head.obj", "assets/new.png"]
+        );
+
+        fs::remove_file(dir.join("new.png")).unwrap();
+        assert_eq!(watcher.poll(1.0), vec!["assets/new.png"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
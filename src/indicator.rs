@@ -0,0 +1,132 @@
+use crate::food::{FoodFactory, FoodTarget, FoodVariant};
+use crate::movement::View;
+use crate::snake::Shnek;
+use macroquad::prelude::*;
+
+/** Screen space markers pointing along the shortest wrapped path to food.
+Food on the screen gets a ring around it, food off the screen gets an arrow
+on the edge of the screen in its direction.
+*/
+pub struct FoodIndicator {
+    pub show_expiring: bool,
+}
+
+impl FoodIndicator {
+    const EDGE_MARGIN: f32 = 40.0; // Distance of arrows from the screen edge in pixels
+    const EXPIRING_TIME: f32 = 5.0; // Show bad food that expires sooner than this (seconds)
+
+    pub fn new() -> Self {
+        Self {
+            show_expiring: true,
+        }
+    }
+
+    pub fn draw(
+        &self,
+        view: &View,
+        player: &Shnek,
+        food_factory: &FoodFactory,
+        nearest_food: Option<FoodTarget>,
+    ) {
+        let camera = view.camera_at(player.get_camera_position());
+        let matrix = camera.matrix();
+        let head = player.get_position();
+
+        if let Some(food) = nearest_food {
+            self.draw_marker(&matrix, head, &food, GOLD);
+        }
+        if self.show_expiring {
+            for food in
+                food_factory.expiring_bad_food(head, player.time_moving, Self::EXPIRING_TIME)
+            {
+                self.draw_marker(&matrix, head, &food, PURPLE);
+            }
+        }
+    }
+
+    fn draw_marker(&self, matrix: &Mat4, head: Vec3, food: &FoodTarget, color: Color) {
+        let screen = vec2(screen_width(), screen_height());
+        let clip = *matrix * (head + food.delta).extend(1.0);
+        let label = match (food.variant, food.time_left) {
+            (FoodVariant::Bad, Some(time_left)) => format!("{:.1} s", time_left),
+            _ => format!("{}", food.distance().round()),
+        };
+
+        match marker_position(clip, screen, Self::EDGE_MARGIN) {
+            Marker::OnScreen(p) => {
+                draw_circle_lines(p.x, p.y, 25.0, 3.0, color);
+                draw_text(&label, p.x + 30.0, p.y, 24.0, color);
+            }
+            Marker::Edge(p, dir) => {
+                // Triangle pointing away from the centre of the screen
+                let side = vec2(-dir.y, dir.x);
+                let tip = p + dir * 20.0;
+                let left = p - dir * 10.0 + side * 14.0;
+                let right = p - dir * 10.0 - side * 14.0;
+                draw_triangle(tip, left, right, color);
+                draw_text(
+                    &label,
+                    p.x - dir.x * 40.0 - 15.0,
+                    p.y - dir.y * 30.0,
+                    24.0,
+                    color,
+                );
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Marker {
+    OnScreen(Vec2),
+    /// Position on the screen edge and the direction (unit vector) of the target.
+    Edge(Vec2, Vec2),
+}
+
+/// Decides where to put the marker for a point in clip space.
+pub fn marker_position(clip: Vec4, screen: Vec2, margin: f32) -> Marker {
+    let half = screen * 0.5;
+    if clip.w > 1e-4 {
+        let ndc = clip.truncate().truncate() / clip.w;
+        let p = vec2(half.x * (1.0 + ndc.x), half.y * (1.0 - ndc.y));
+        if p.x >= margin && p.x <= screen.x - margin && p.y >= margin && p.y <= screen.y - margin {
+            return Marker::OnScreen(p);
+        }
+    }
+    // Not dividing by w keeps the direction correct for points behind the camera
+    let dir = vec2(clip.x, -clip.y)
+        .try_normalize()
+        .unwrap_or(vec2(0.0, 1.0));
+    // Scale the direction so it touches the rectangle inset by margin
+    let bounds = half - vec2(margin, margin);
+    let scale = (bounds.x / dir.x.abs().max(1e-6)).min(bounds.y / dir.y.abs().max(1e-6));
+    Marker::Edge(half + dir * scale, dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_marker_on_screen() {
+        let screen = vec2(800., 600.);
+        let marker = marker_position(vec4(0., 0., 0.5, 1.0), screen, 40.0);
+        assert_eq!(marker, Marker::OnScreen(vec2(400., 300.)));
+        let marker = marker_position(vec4(1.0, 1.0, 1.0, 2.0), screen, 40.0);
+        assert_eq!(marker, Marker::OnScreen(vec2(600., 150.)));
+    }
+
+    #[test]
+    fn test_marker_on_edge() {
+        let screen = vec2(800., 600.);
+        // Far to the right
+        let marker = marker_position(vec4(5.0, 0., 0.5, 1.0), screen, 40.0);
+        assert_eq!(marker, Marker::Edge(vec2(760., 300.), vec2(1., 0.)));
+        // Behind the camera and to the left, the arrow still points left
+        let marker = marker_position(vec4(-0.5, 0., 0.5, -1.0), screen, 40.0);
+        assert_eq!(marker, Marker::Edge(vec2(40., 300.), vec2(-1., 0.)));
+        // Above
+        let marker = marker_position(vec4(0., 3.0, 0.5, 1.0), screen, 40.0);
+        assert_eq!(marker, Marker::Edge(vec2(400., 40.), vec2(0., -1.)));
+    }
+}
@@ -0,0 +1,236 @@
+use crate::score::{sort_best_first, ScoreEntry};
+
+/// Which games the score screen lists.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Tab {
+    AllTime,
+    /// Played today (UTC), like the dates on the score screen.
+    Today,
+    /// Played since the game was started.
+    Session,
+}
+
+impl Tab {
+    pub const ALL: [Tab; 3] = [Tab::AllTime, Tab::Today, Tab::Session];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tab::AllTime => "All",
+            Tab::Today => "Today",
+            Tab::Session => "Session",
+        }
+    }
+
+    fn includes(self, entry: &ScoreEntry, now: u64, session_start: u64) -> bool {
+        match self {
+            Tab::AllTime => true,
+            Tab::Today => entry.time / 86400 == now / 86400,
+            Tab::Session => entry.time >= session_start,
+        }
+    }
+}
+
+/** What the score screen shows: a tab, a game mode and a page of the best games.
+The mouse wheel scrolls through the pages. The scores are read once by `show`
+when the screen is opened, the rows are sorted again only when the tab or the
+mode changes.
+*/
+pub struct Leaderboard {
+    pub tab: Tab,
+    /// `None` lists all modes.
+    pub mode: Option<String>,
+    pub page: usize,
+    entries: Vec<ScoreEntry>,
+    /// The game modes that have scores, in the order they were first played.
+    modes: Vec<String>,
+    /// The entries of the tab, best first. The position is the rank.
+    rows: Vec<ScoreEntry>,
+    /// Seconds since 1970 when the screen was opened, for the today tab.
+    now: u64,
+    session_start: u64,
+}
+
+impl Leaderboard {
+    pub const ROWS: usize = 10;
+
+    pub fn new() -> Self {
+        Self {
+            tab: Tab::AllTime,
+            mode: None,
+            page: 0,
+            entries: Vec::new(),
+            modes: Vec::new(),
+            rows: Vec::new(),
+            now: 0,
+            session_start: 0,
+        }
+    }
+
+    /// All time, all modes, on the page of the last game if there is one.
+    pub fn show(
+        &mut self,
+        entries: Vec<ScoreEntry>,
+        last: Option<&ScoreEntry>,
+        now: u64,
+        session_start: u64,
+    ) {
+        self.modes = modes(&entries);
+        self.entries = entries;
+        self.now = now;
+        self.session_start = session_start;
+        self.mode = None;
+        self.set_tab(Tab::AllTime);
+        if let Some(position) = last.and_then(|last| self.rows.iter().position(|row| row == last)) {
+            self.page = position / Self::ROWS;
+        }
+    }
+
+    pub fn set_tab(&mut self, tab: Tab) {
+        self.tab = tab;
+        self.sort_rows();
+    }
+
+    /// Only worth a button with more than one mode to choose from.
+    pub fn has_modes(&self) -> bool {
+        self.modes.len() > 1
+    }
+
+    /// The next game mode that has scores, after the last one back to all modes.
+    pub fn next_mode(&mut self) {
+        let next = match &self.mode {
+            None => 0,
+            Some(mode) => self
+                .modes
+                .iter()
+                .position(|m| m == mode)
+                .map_or(0, |i| i + 1),
+        };
+        self.mode = self.modes.get(next).cloned();
+        self.sort_rows();
+    }
+
+    fn sort_rows(&mut self) {
+        self.page = 0;
+        self.rows = self
+            .entries
+            .iter()
+            .filter(|entry| self.tab.includes(entry, self.now, self.session_start))
+            .filter(|entry| self.mode.as_ref().is_none_or(|mode| entry.mode == *mode))
+            .cloned()
+            .collect();
+        sort_best_first(&mut self.rows);
+    }
+
+    /// At least one, an empty list still has a page.
+    pub fn page_count(&self) -> usize {
+        self.rows.len().div_ceil(Self::ROWS).max(1)
+    }
+
+    /// Moves `by` pages, staying on the first and last page.
+    pub fn turn(&mut self, by: i32) {
+        let last = self.page_count() - 1;
+        self.page = (self.page as i32 + by).clamp(0, last as i32) as usize;
+    }
+
+    /// Rank of the first row on the page and the rows on it.
+    pub fn page(&self) -> (usize, &[ScoreEntry]) {
+        let start = (self.page * Self::ROWS).min(self.rows.len());
+        let end = (start + Self::ROWS).min(self.rows.len());
+        (start + 1, &self.rows[start..end])
+    }
+}
+
+/// The game modes that have scores, in the order they were first played.
+fn modes(entries: &[ScoreEntry]) -> Vec<String> {
+    let mut modes: Vec<String> = Vec::new();
+    for entry in entries {
+        if !modes.contains(&entry.mode) {
+            modes.push(entry.mode.clone());
+        }
+    }
+    modes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::score::DEFAULT_MODE;
+
+    fn entry(score: i32, time: u64) -> ScoreEntry {
+        mode_entry(score, time, DEFAULT_MODE)
+    }
+
+    fn mode_entry(score: i32, time: u64, mode: &str) -> ScoreEntry {
+        ScoreEntry {
+            score,
+            name: String::new(),
+            time,
+            length: 0,
+            duration: 0,
+            mode: String::from(mode),
+        }
+    }
+
+    #[test]
+    fn test_tabs() {
+        let day = 86400;
+        let entries = vec![
+            mode_entry(5, 10 * day, DEFAULT_MODE),
+            mode_entry(8, 11 * day + 5, DEFAULT_MODE),
+            mode_entry(3, 11 * day + 50, "zen"),
+            mode_entry(9, 11 * day + 100, DEFAULT_MODE),
+        ];
+        let scores = |board: &Leaderboard| -> Vec<i32> {
+            board.page().1.iter().map(|row| row.score).collect()
+        };
+
+        let mut board = Leaderboard::new();
+        board.show(entries, None, 11 * day + 200, 11 * day + 40);
+        assert_eq!(scores(&board), [9, 8, 5, 3]);
+        board.set_tab(Tab::Today);
+        assert_eq!(scores(&board), [9, 8, 3]);
+        board.set_tab(Tab::Session);
+        assert_eq!(scores(&board), [9, 3]);
+
+        assert!(board.has_modes());
+        board.next_mode();
+        assert_eq!(board.mode.as_deref(), Some(DEFAULT_MODE));
+        assert_eq!(scores(&board), [9]);
+        board.set_tab(Tab::AllTime);
+        assert_eq!(scores(&board), [9, 8, 5]);
+        board.next_mode();
+        assert_eq!(scores(&board), [3]);
+        board.next_mode();
+        assert_eq!(board.mode, None);
+        assert_eq!(scores(&board), [9, 8, 5, 3]);
+
+        // One mode, nothing to choose
+        board.show(vec![entry(1, 0)], None, 0, 0);
+        assert!(!board.has_modes());
+    }
+
+    #[test]
+    fn test_paging() {
+        let entries: Vec<ScoreEntry> = (1..=25).map(|score| entry(score, 0)).collect();
+        let mut board = Leaderboard::new();
+        board.show(entries.clone(), None, 0, 0);
+        assert_eq!(board.page_count(), 3);
+
+        board.turn(5);
+        assert_eq!(board.page, 2);
+        let (rank, page) = board.page();
+        assert_eq!(rank, 21);
+        assert_eq!(page.len(), 5);
+        assert_eq!(page[0].score, 5);
+        board.turn(-1);
+        assert_eq!(board.page().0, 11);
+
+        // Opens on the page of the last game
+        board.show(entries.clone(), Some(&entries[2]), 0, 0);
+        assert_eq!(board.page, 2);
+
+        board.show(Vec::new(), None, 0, 0);
+        assert_eq!(board.page_count(), 1);
+        assert!(board.page().1.is_empty());
+    }
+}
@@ -1,112 +1,329 @@
-use crate::button::{
-    load_button_style, load_font, load_label_style, load_window_background, load_window_style,
-    loading_sound,
-};
-use crate::draw_utils::SPACE_SIZE;
+use crate::assets::{Assets, LoadReport};
+use crate::audio::Audio;
+use crate::button::{load_button_style, load_label_style, load_ui_skin, load_window_style};
+use crate::draw_utils::BACKGROUND_COLOR;
+use crate::events::{DeathCause, Events, GameEvent};
 use crate::food::FoodFactory;
-use crate::menu::{draw_status, help, main_menu, paused, running, score_menu, FPSCounter};
-use crate::models3d::Model3D;
+use crate::food_hint::FoodHint;
+use crate::hot_reload::AssetWatcher;
+use crate::indicator::FoodIndicator;
+use crate::leaderboard::Leaderboard;
+use crate::manifest::AssetLibrary;
+use crate::menu::{
+    achievements_menu, draw_status, game_over, help, load_errors, main_menu, name_entry_menu,
+    new_profile_menu, paused, profiles_menu, running, score_menu, skins_menu, stats_menu,
+    FPSCounter,
+};
+use crate::models3d::model3d::{set_chunk_limits, ChunkLimits};
+use crate::models3d::{Culling, Lighting};
+use crate::movement::CameraMode;
+use crate::orientation::Orientation;
+use crate::profiles::{NameEntry, ProfileData, Profiles};
+use crate::radar::Radar;
+use crate::skins::SkinMenu;
+use crate::state::{GameState, Hook, StateMachine, Transition};
+use crate::summary::{new_seed, RunSummary};
+use crate::toasts::Toasts;
 use macroquad::miniquad::window::set_window_size;
 use macroquad::{
+    conf::Conf,
     prelude::*,
     ui::{root_ui, Skin},
 };
 
+mod achievements;
+mod assets;
+mod audio;
+mod benchmark;
 mod button;
 mod draw_utils;
+mod events;
 mod food;
+mod food_hint;
+mod hot_reload;
+mod indicator;
+mod leaderboard;
+mod manifest;
 mod menu;
 mod models3d;
 mod movement;
+mod orientation;
+mod profiles;
+mod radar;
 mod score;
+mod settings;
+mod skins;
 mod snake;
+mod state;
+mod stats;
+mod storage;
+mod summary;
+mod toasts;
 
-#[derive(Debug, PartialEq, Copy, Clone)]
-enum GameState {
-    MainMenu,
-    Running,
-    Paused,
-    GameOver,
-    Score,
+/// Sound effects for what happened in the game this frame.
+fn play_event_sounds(events: &Events, audio: &Audio, library: &AssetLibrary) {
+    let mut eaten = false;
+    for event in events.iter() {
+        match event {
+            GameEvent::FoodEaten { .. } => eaten = true,
+            GameEvent::Died {
+                cause: DeathCause::TailCollision,
+            } => audio.play(library.sound("sfx.crash")),
+            _ => {}
+        }
+    }
+    // Once, even when more food was eaten at the same time
+    if eaten {
+        audio.play(library.sound("sfx.eat"));
+    }
 }
 
-#[macroquad::main("Shnek")]
+async fn ui_skin(library: &AssetLibrary) -> Skin {
+    let window_style = load_window_style(library.image("ui.window_background")).await;
+    let font = library.font("ui.font");
+    let button_style = load_button_style(font, library.ui().button_font_size).await;
+    let label_style = load_label_style(font, library.ui().label_font_size).await;
+    load_ui_skin(window_style, button_style, label_style).await
+}
+
+fn window_conf() -> Conf {
+    Conf {
+        miniquad_conf: macroquad::miniquad::conf::Conf {
+            window_title: String::from("Shnek"),
+            ..Default::default()
+        },
+        // macroquad's own defaults. The batch buffer is uploaded for every draw
+        // call, so bigger buffers cost bandwidth for the small meshes that make up
+        // most of the game, and bigger models are split into chunks anyway.
+        draw_call_vertex_capacity: 10000,
+        draw_call_index_capacity: 5000,
+        ..Default::default()
+    }
+}
+
+/// `--dev` reloads assets when their files change.
+fn is_dev_mode() -> bool {
+    std::env::args().any(|arg| arg == "--dev")
+}
+
+#[macroquad::main(window_conf)]
 async fn main() {
     set_window_size(1600, 1200);
-    let head_model = Model3D::from_file("assets/head/snake_head.obj");
-    let body_model = Model3D::from_file("assets/body/snake_body.obj");
+    // Models are split into meshes that fit into the buffers macroquad runs with
+    set_chunk_limits(ChunkLimits::from_conf(&window_conf()));
+    let assets = Assets::from_args();
+    // Scores, profiles and settings, outside of the assets
+    let data_dir = storage::data_dir();
+    let mut report = LoadReport::new();
+    let mut library = AssetLibrary::load(&assets, &mut report).await;
+
+    let mut view = movement::View::new();
+    let mut lighting = Lighting::new(BACKGROUND_COLOR, &mut report);
+    // Copies of the world needed to fill the view, changes with the fog
+    let repeat = view.repeat(lighting.fog.distance());
 
-    let mut player = snake::Shnek::new(&head_model, &body_model, 3);
+    let mut player = snake::Shnek::new(
+        library.model("snake.head"),
+        library.model("snake.body"),
+        3,
+        repeat,
+    );
     player.set_position(0., 0., 0.);
-    player.set_direction(vec3(1., 0., 0.), vec3(0., 0., 1.));
+    player.set_orientation(Orientation::from_forward_up(
+        vec3(1., 0., 0.),
+        vec3(0., 0., 1.),
+    ));
 
-    let food_model = Model3D::from_file("assets/apfel/apfel.obj");
-    let bad_food_model = Model3D::from_file("assets/bad_apfel/bad_apfel.obj");
-    let poop_model = Model3D::from_file("assets/poop/poop.obj");
-    let mut food_factory = FoodFactory::new(&food_model, &bad_food_model, &poop_model);
+    let mut food_factory = FoodFactory::new(&library, repeat);
 
-    let mut view = movement::View::new();
+    if std::env::args().any(|arg| arg == "--benchmark") {
+        benchmark::run(&library, &food_factory, &lighting).await;
+        return;
+    }
 
-    let mut game_state = GameState::MainMenu;
+    let mut states = StateMachine::new();
+    let mut summary = RunSummary::new(0, player.start_length);
+    // Set by "Retry seed" on the game over screen
+    let mut next_seed = None;
 
     let mut high_score = 0;
 
-    let mut score_file = score::Score::new();
-
-    let window_style =
-        load_window_style(load_window_background("assets/Solid_black.png").await).await;
-    let button_style = load_button_style(load_font("assets/yoster.ttf").await).await;
-    let label_style = load_label_style(load_font("assets/yoster.ttf").await).await;
-    let collision_sound = loading_sound("assets/spongebob-fog-horn.wav").await;
-    let eat_sound = loading_sound("assets/eating-sound-effect.wav").await;
-    let click = loading_sound("assets/computer-mouse-click.wav").await;
-
-    let ui_skin = Skin {
-        window_style,
-        button_style,
-        label_style,
-        ..root_ui().default_skin()
-    };
-    root_ui().push_skin(&ui_skin);
-
-    let mut food_distance = SPACE_SIZE * 3.0;
+    let mut score_file = score::Score::new(&data_dir);
+    let mut leaderboard = Leaderboard::new();
+
+    let mut profiles = Profiles::load(&data_dir, &mut report);
+    let mut profile = ProfileData::load(profiles.current_dir());
+    let mut profile_shown = 0;
+    // For new profiles and high scores
+    let mut name_entry = NameEntry::new("");
+
+    profile.skin.apply(&mut player, &library, score_file.best());
+    let mut skin_menu = SkinMenu::new();
+    // Unlocks skins, read from the score file when the skins menu is opened
+    let mut best_score = 0;
+
+    let mut stats_message = None;
+    let mut toasts = Toasts::new();
+
+    let mut audio = Audio::new(profile.settings.audio);
+    let food_hint = FoodHint::new().await;
+
+    if !report.is_empty() {
+        for failure in &report.failures {
+            eprintln!("failed to load {}", failure);
+        }
+        if !load_errors(&report).await {
+            return;
+        }
+    }
+
+    root_ui().push_skin(&ui_skin(&library).await);
+    let mut watcher = assets
+        .override_dir()
+        .filter(|_| is_dev_mode())
+        .map(AssetWatcher::new);
+
+    let mut nearest_food = None;
+    let mut events = Events::new();
+    let food_indicator = FoodIndicator::new();
     let mut fps_counter = FPSCounter::new();
+    let mut radar = Radar::new();
+    let mut culling_enabled = true;
     loop {
-        main_menu(&mut game_state, &click, &mut score_file);
+        let dt = get_frame_time();
+        if let Some(changed) = watcher.as_mut().map(|watcher| watcher.poll(dt)) {
+            if !changed.is_empty() {
+                let mut report = LoadReport::new();
+                let reloaded = library.reload(&assets, &changed, &mut report).await;
+                for failure in &report.failures {
+                    eprintln!("failed to reload {}", failure);
+                }
+                for (old, new) in &reloaded.models {
+                    player.replace_model(old, new);
+                    food_factory.replace_model(old, new);
+                    skin_menu.replace_model(old, new);
+                }
+                if reloaded.ui {
+                    root_ui().pop_skin();
+                    root_ui().push_skin(&ui_skin(&library).await);
+                }
+            }
+        }
+        // Looked up every frame, it can be replaced by a reload
+        let click = library.sound("sfx.click");
+        events.clear();
+
+        let game_state = states.current();
+        let music = match game_state {
+            GameState::MainMenu
+            | GameState::Score
+            | GameState::Skins
+            | GameState::Stats
+            | GameState::Achievements
+            | GameState::Profiles
+            | GameState::NewProfile => "music.menu",
+            GameState::Running | GameState::Paused | GameState::GameOver | GameState::NameEntry => {
+                "music.game"
+            }
+        };
+        audio.play_music(music, library.sound(music));
+        audio.set_ducked(game_state == GameState::Paused || game_state == GameState::GameOver);
+        audio.update(dt);
+
+        main_menu(&mut states, &audio, click, &profiles.current);
 
         if is_key_pressed(KeyCode::Escape) || is_key_pressed(KeyCode::Space) {
-            game_state = match game_state {
-                GameState::MainMenu => GameState::MainMenu,
-                GameState::Running => GameState::Paused,
-                GameState::Paused => GameState::Running,
-                GameState::GameOver => GameState::GameOver,
-                GameState::Score => GameState::GameOver,
-            };
+            if let Some(transition) = game_state.back() {
+                states.request(transition);
+            }
+        }
+
+        let shortcuts = !game_state.takes_text();
+        if shortcuts && is_key_pressed(KeyCode::C) {
+            view.next_camera_mode();
+        }
+        if shortcuts && is_key_pressed(KeyCode::R) {
+            radar.next_mode();
+        }
+        if shortcuts && is_key_pressed(KeyCode::L) {
+            lighting.next_mode();
+        }
+        if shortcuts && is_key_pressed(KeyCode::M) {
+            audio.settings.muted = !audio.settings.muted;
+        }
+        if shortcuts && is_key_pressed(KeyCode::H) {
+            audio.settings.food_hint = !audio.settings.food_hint;
+        }
+        if shortcuts && is_key_pressed(KeyCode::K) {
+            culling_enabled = !culling_enabled;
+        }
+        if shortcuts && is_key_pressed(KeyCode::F) {
+            lighting.toggle_fog();
+        }
+        if is_key_pressed(KeyCode::V)
+            && (game_state == GameState::Paused || game_state == GameState::GameOver)
+        {
+            view.toggle_orbit();
+        }
+        if game_state == GameState::Running && view.camera_mode() == CameraMode::Orbit {
+            view.toggle_orbit();
         }
 
-        let dt = get_frame_time();
         fps_counter.add_frame_dt(dt);
+        view.orbit(dt);
+        view.update_camera(dt);
         let score = player.get_score();
 
         running(
-            &mut game_state,
-            &eat_sound,
-            &collision_sound,
+            &mut states,
             &mut player,
             &mut view,
             &mut food_factory,
+            &mut events,
             dt,
-            &mut food_distance,
+            &mut nearest_food,
+        );
+        play_event_sounds(&events, &audio, &library);
+        if game_state == GameState::Running {
+            summary.record(&events, player.get_position(), player.get_length(), dt);
+            let unlocked =
+                profile
+                    .achievements
+                    .update(library.achievements(), &summary, &profile.stats);
+            if !unlocked.is_empty() {
+                let saved = profile.achievements.save(&profile.dir);
+                toasts.report_save_error("achievements", saved);
+            }
+            for name in unlocked {
+                toasts.push(format!("Achievement unlocked: {}", name));
+            }
+        }
+        food_hint.update(
+            &audio,
+            &view,
+            &player,
+            &food_factory,
+            game_state == GameState::Running,
         );
 
         // Set the camera to follow the player
-        view.set_camera(player.get_camera_position());
+        let camera = view.camera(player.get_camera_position());
+        set_camera(&camera);
 
-        clear_background(Color::new(0.68, 0.85, 0.90, 1.0));
+        clear_background(BACKGROUND_COLOR);
         // draw
 
-        food_factory.draw();
-        player.draw();
+        // Nothing is visible behind the fog or the far plane
+        let view_distance = view.view_distance(lighting.fog.distance());
+        let mut culling = Culling::from_camera(&camera, view_distance);
+        culling.enabled = culling_enabled;
+        // Follows the fog and the shape of the window
+        player.set_repeat(culling.repeat());
+        food_factory.set_repeat(culling.repeat());
+        lighting.apply(camera.position);
+        let mut draw_stats = food_factory.draw(&culling);
+        draw_stats += player.draw(&culling);
+        lighting.reset();
 
         // Back to screen space, render some text
         set_default_camera();
@@ -114,32 +331,168 @@ async fn main() {
         draw_status(
             score,
             high_score,
-            food_distance,
+            nearest_food,
             food_factory.food_count(),
             food_factory.max_food as usize,
             &fps_counter,
+            view.camera_mode(),
+            draw_stats,
         ); // TODO: max_food should be usize
+        radar.draw(&view, &player, &food_factory, nearest_food);
+        if game_state == GameState::Running {
+            food_indicator.draw(&view, &player, &food_factory, nearest_food);
+        }
 
         // Pause menu
 
         paused(
-            &mut game_state,
-            &click,
-            &mut high_score,
-            &mut player,
-            &mut view,
-            &mut food_factory,
-            &food_model,
-            &bad_food_model,
-            &poop_model,
+            &mut states,
+            &mut audio,
+            click,
+            high_score,
             &mut score_file,
+            &profiles.current,
+            &summary,
         );
+        game_over(&mut states, &mut audio, click, &summary, &mut next_seed);
 
         // Help on the bottom
         help();
 
         //Score screen
-        score_menu(&mut game_state, &click, high_score, &mut score_file);
+        score_menu(&mut states, &audio, click, &score_file, &mut leaderboard);
+
+        skins_menu(
+            &mut states,
+            &audio,
+            click,
+            &mut skin_menu,
+            &mut profile.skin,
+            &mut player,
+            &library,
+            best_score,
+            dt,
+            &mut toasts,
+        );
+
+        stats_menu(
+            &mut states,
+            &audio,
+            click,
+            &profile,
+            &profiles.current,
+            &mut stats_message,
+        );
+        achievements_menu(&mut states, &audio, click, &library, &profile.achievements);
+        profiles_menu(
+            &mut states,
+            &audio,
+            click,
+            &mut profiles,
+            &mut profile_shown,
+            &mut toasts,
+        );
+        new_profile_menu(
+            &mut states,
+            &audio,
+            click,
+            &mut profiles,
+            &mut name_entry,
+            &mut toasts,
+        );
+        name_entry_menu(
+            &mut states,
+            &audio,
+            click,
+            &mut name_entry,
+            &mut score_file,
+            high_score,
+            &profiles.current,
+            &summary,
+            &mut toasts,
+        );
+        // On top of every screen
+        toasts.draw(dt);
+
+        for hook in states.apply() {
+            match hook {
+                // A new game, also after Reset
+                Hook::Enter(GameState::Running) => {
+                    let seed = next_seed.take().unwrap_or_else(new_seed);
+                    // Food is placed with macroquad's random numbers
+                    rand::srand(seed);
+                    summary = RunSummary::new(seed, player.start_length);
+                    high_score = 0;
+                    player.reset();
+                    view.reset();
+                    food_factory = FoodFactory::new(&library, view.repeat(lighting.fog.distance()));
+                    score_file.reset();
+                    for _ in 0..player.start_length {
+                        player.add_segment();
+                    }
+                }
+                // Died or reset
+                Hook::Exit(GameState::Running) => {
+                    profile.stats.add_run(&summary);
+                    toasts.report_save_error("stats", profile.stats.save(&profile.dir));
+                    let unlocked = profile
+                        .achievements
+                        .update_finished(library.achievements(), &profile.stats);
+                    for name in unlocked {
+                        toasts.push(format!("Achievement unlocked: {}", name));
+                    }
+                    // Also progress that didn't unlock anything yet
+                    let saved = profile.achievements.save(&profile.dir);
+                    toasts.report_save_error("achievements", saved);
+                }
+                Hook::Enter(GameState::GameOver) => {
+                    if score_file.is_top(high_score) {
+                        name_entry = NameEntry::new(&profiles.current);
+                        states.request(Transition::Push(GameState::NameEntry));
+                    } else {
+                        let written =
+                            score_file.write(high_score as usize, &profiles.current, &summary);
+                        toasts.report_save_error("the score", written);
+                    }
+                }
+                Hook::Enter(GameState::Profiles) => {
+                    profile_shown = profiles
+                        .names
+                        .iter()
+                        .position(|name| *name == profiles.current)
+                        .unwrap_or(0);
+                }
+                Hook::Enter(GameState::Score) => {
+                    leaderboard.show(
+                        score_file.entries(),
+                        score_file.last.as_ref(),
+                        score::now(),
+                        score_file.session_start,
+                    );
+                }
+                Hook::Enter(GameState::NewProfile) => name_entry = NameEntry::new(""),
+                Hook::Enter(GameState::Skins) => {
+                    best_score = score_file.best().max(high_score);
+                }
+                Hook::Exit(GameState::Skins) => skin_menu.close(),
+                Hook::Exit(GameState::Stats) => stats_message = None,
+                _ => {}
+            }
+        }
+
+        // Changed by M, H or the sliders in the pause menu. A slider is saved once
+        // it is let go, not for every step while it is dragged.
+        if audio.settings != profile.settings.audio && !is_mouse_button_down(MouseButton::Left) {
+            profile.settings.audio = audio.settings;
+            toasts.report_save_error("settings", profile.settings.save(&profile.dir));
+        }
+
+        // Picked or created in the profiles menu
+        if profiles.current_dir() != profile.dir {
+            profile = ProfileData::load(profiles.current_dir());
+            audio.settings = profile.settings.audio;
+            profile.skin.apply(&mut player, &library, score_file.best());
+        }
 
         next_frame().await;
     }
@@ -0,0 +1,558 @@
+use crate::assets::{plain_image, silent_sound, AssetError, Assets, LoadReport};
+use crate::audio::{device_available, GameSound};
+use crate::button::{load_font, load_ui_image, loading_sound};
+use crate::models3d::{Model3D, ModelOptions};
+use macroquad::color::{Color, BLACK};
+use macroquad::math::{EulerRot, Quat};
+use macroquad::texture::{FilterMode, Image};
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// The manifest the game was built with, used when `assets/manifest.toml` can't be read.
+const BUILT_IN: &str = include_str!("../assets/manifest.toml");
+
+/** Which files the game loads and how, by id (`snake.head`, `sfx.eat`, `ui.font`, ...).
+Read from `assets/manifest.toml`, file names are relative to the assets directory.
+*/
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)]
+    pub models: BTreeMap<String, ModelEntry>,
+    #[serde(default)]
+    pub sounds: BTreeMap<String, SoundEntry>,
+    #[serde(default)]
+    pub fonts: BTreeMap<String, FileEntry>,
+    #[serde(default)]
+    pub images: BTreeMap<String, FileEntry>,
+    #[serde(default)]
+    pub ui: UiEntry,
+    #[serde(default)]
+    pub skins: BTreeMap<String, SkinEntry>,
+    #[serde(default)]
+    pub achievements: BTreeMap<String, AchievementEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelEntry {
+    pub file: String,
+    #[serde(default = "one")]
+    pub scale: f32,
+    /// Degrees around X, Y and Z, applied in this order.
+    #[serde(default)]
+    pub rotation: [f32; 3],
+    #[serde(default)]
+    pub filter: Filter,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    /// Pixelated, like Minecraft.
+    #[default]
+    Nearest,
+    Linear,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SoundEntry {
+    pub file: String,
+    #[serde(default = "one")]
+    pub volume: f32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileEntry {
+    pub file: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct UiEntry {
+    pub button_font_size: u16,
+    pub label_font_size: u16,
+}
+
+impl Default for UiEntry {
+    fn default() -> Self {
+        Self {
+            button_font_size: 64,
+            label_font_size: 28,
+        }
+    }
+}
+
+/// A look for the snake the player can pick.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct SkinEntry {
+    pub name: String,
+    /// Model ids of the head and the body segments.
+    pub head: String,
+    pub body: String,
+    /// Red, green and blue from 0 to 255, multiplied with the model colours.
+    pub tint: [u8; 3],
+    /// Best score needed before the skin can be picked.
+    pub unlock_score: i32,
+}
+
+impl Default for SkinEntry {
+    fn default() -> Self {
+        Self {
+            name: String::from("Classic"),
+            head: String::from("snake.head"),
+            body: String::from("snake.body"),
+            tint: [255; 3],
+            unlock_score: 0,
+        }
+    }
+}
+
+impl SkinEntry {
+    pub fn tint_color(&self) -> Color {
+        let [r, g, b] = self.tint;
+        Color::from_rgba(r, g, b, 255)
+    }
+}
+
+/// An achievement, unlocked when `stat` reaches `goal`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AchievementEntry {
+    pub name: String,
+    pub description: String,
+    pub stat: Stat,
+    pub goal: f32,
+}
+
+/// What an achievement counts, in one game or over all games.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stat {
+    /// Longest snake in one game.
+    Length,
+    /// Seconds alive in one game.
+    TimeAlive,
+    /// Seconds in one game without boosting in between.
+    UnboostedTime,
+    /// Axes the snake went around the cube on in one game, up to 3.
+    AxesWrapped,
+    /// The rest count over all games.
+    ApplesEaten,
+    PoopEaten,
+    GamesPlayed,
+    Distance,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+impl Manifest {
+    pub const PATH: &'static str = "assets/manifest.toml";
+
+    pub fn parse(path: &str, text: &str) -> Result<Self, AssetError> {
+        toml::from_str(text).map_err(|error| AssetError::Manifest {
+            path: path.to_string(),
+            message: error.message().to_string(),
+        })
+    }
+
+    /// Reads the manifest, falls back to the built-in one if it is missing or broken.
+    pub fn load(assets: &Assets, report: &mut LoadReport) -> Self {
+        let manifest = assets.read(Self::PATH).and_then(|data| {
+            let text = String::from_utf8_lossy(&data);
+            Self::parse(Self::PATH, &text)
+        });
+        report
+            .check(manifest)
+            .unwrap_or_else(|| Self::parse("built-in manifest", BUILT_IN).unwrap())
+    }
+}
+
+impl ModelEntry {
+    pub fn options(&self) -> ModelOptions {
+        let [x, y, z] = self.rotation.map(f32::to_radians);
+        ModelOptions {
+            scale: self.scale,
+            rotation: Quat::from_euler(EulerRot::XYZ, x, y, z),
+            filter: match self.filter {
+                Filter::Nearest => FilterMode::Nearest,
+                Filter::Linear => FilterMode::Linear,
+            },
+        }
+    }
+}
+
+/// Path of a manifest file in the assets.
+fn asset_path(file: &str) -> String {
+    format!("assets/{}", file)
+}
+
+/// Loads a model and lists the files it was read from, also when loading failed.
+fn load_model(
+    assets: &Assets,
+    entry: &ModelEntry,
+    report: &mut LoadReport,
+) -> (Result<Model3D, AssetError>, Vec<String>) {
+    let path = asset_path(&entry.file);
+    let files = RefCell::new(vec![path.clone()]);
+    let read_file = |file: &str| {
+        files.borrow_mut().push(file.to_string());
+        assets.read(file)
+    };
+    let model = assets
+        .read(&path)
+        .and_then(|data| Model3D::from_memory(&path, &data, read_file, &entry.options(), report));
+    (model, files.into_inner())
+}
+
+/** All assets from the manifest, loaded at startup and looked up by id.
+Ids the game asks for that are missing get placeholders, so a broken
+manifest shows up on the load error screen instead of crashing.
+Models are shared with everything drawing them, `reload` hands out
+the replaced ones so their users can switch over.
+*/
+pub struct AssetLibrary {
+    manifest: Manifest,
+    models: BTreeMap<String, Rc<Model3D>>,
+    /// Every file a model was read from (materials, textures, buffers), by model id.
+    model_files: BTreeMap<String, Vec<String>>,
+    sounds: BTreeMap<String, GameSound>,
+    fonts: BTreeMap<String, Vec<u8>>,
+    images: BTreeMap<String, Image>,
+}
+
+/// What `AssetLibrary::reload` replaced.
+#[derive(Default)]
+pub struct Reloaded {
+    /// Old and new version of every reloaded model.
+    pub models: Vec<(Rc<Model3D>, Rc<Model3D>)>,
+    /// Fonts, images or UI sizes changed, the UI skin has to be built again.
+    pub ui: bool,
+}
+
+impl AssetLibrary {
+    /// Ids used by the game, reported if the manifest does not have them.
+    const REQUIRED_MODELS: [&'static str; 5] = [
+        "snake.head",
+        "snake.body",
+        "food.normal",
+        "food.bad",
+        "food.poop",
+    ];
+    const REQUIRED_SOUNDS: [&'static str; 5] = [
+        "sfx.eat",
+        "sfx.crash",
+        "sfx.click",
+        "music.menu",
+        "music.game",
+    ];
+
+    pub async fn load(assets: &Assets, report: &mut LoadReport) -> Self {
+        let mut library = Self {
+            manifest: Manifest::load(assets, report),
+            models: BTreeMap::new(),
+            model_files: BTreeMap::new(),
+            sounds: BTreeMap::new(),
+            fonts: BTreeMap::new(),
+            images: BTreeMap::new(),
+        };
+        library.update(assets, None, report).await;
+
+        for id in Self::REQUIRED_MODELS {
+            if !library.models.contains_key(id) {
+                report.push(AssetError::UnknownId(id.to_string()));
+                let placeholder = Rc::new(Model3D::placeholder());
+                library.models.insert(id.to_string(), placeholder);
+            }
+        }
+        let skin_models = library
+            .manifest
+            .skins
+            .values()
+            .flat_map(|skin| [&skin.head, &skin.body]);
+        for id in skin_models {
+            if !library.models.contains_key(id) {
+                report.push(AssetError::UnknownId(id.clone()));
+            }
+        }
+        for id in Self::REQUIRED_SOUNDS {
+            if !library.sounds.contains_key(id) {
+                report.push(AssetError::UnknownId(id.to_string()));
+                let sound = if device_available() {
+                    Some(silent_sound().await)
+                } else {
+                    None
+                };
+                library
+                    .sounds
+                    .insert(id.to_string(), GameSound { sound, volume: 0.0 });
+            }
+        }
+        library
+    }
+
+    /// Loads again everything that was read from one of the `changed` asset paths,
+    /// everything if the manifest changed. Assets that fail to load keep their old version.
+    pub async fn reload(
+        &mut self,
+        assets: &Assets,
+        changed: &[String],
+        report: &mut LoadReport,
+    ) -> Reloaded {
+        if changed.iter().any(|path| path == Manifest::PATH) {
+            let manifest = assets
+                .read(Manifest::PATH)
+                .and_then(|data| Manifest::parse(Manifest::PATH, &String::from_utf8_lossy(&data)));
+            if let Some(manifest) = report.check(manifest) {
+                self.manifest = manifest;
+                let mut reloaded = self.update(assets, None, report).await;
+                reloaded.ui = true; // Font sizes may have changed
+                return reloaded;
+            }
+        }
+        self.update(assets, Some(changed), report).await
+    }
+
+    /// Loads the assets of the manifest that use one of the `changed` paths, or all of them.
+    async fn update(
+        &mut self,
+        assets: &Assets,
+        changed: Option<&[String]>,
+        report: &mut LoadReport,
+    ) -> Reloaded {
+        let uses = |files: &[String]| match changed {
+            Some(changed) => files.iter().any(|file| changed.contains(file)),
+            None => true,
+        };
+        let mut reloaded = Reloaded::default();
+
+        for (id, entry) in &self.manifest.models {
+            let files = match self.model_files.get(id) {
+                Some(files) => files.clone(),
+                None => vec![asset_path(&entry.file)],
+            };
+            if !uses(&files) {
+                continue;
+            }
+            let (model, files) = load_model(assets, entry, report);
+            self.model_files.insert(id.clone(), files);
+            let model = match (report.check(model), self.models.get(id)) {
+                (Some(model), _) => Rc::new(model),
+                (None, Some(_)) => continue, // Keep the one that worked
+                (None, None) => Rc::new(Model3D::placeholder()),
+            };
+            if let Some(old) = self.models.insert(id.clone(), model.clone()) {
+                reloaded.models.push((old, model));
+            }
+        }
+
+        for (id, entry) in &self.manifest.sounds {
+            let path = asset_path(&entry.file);
+            if !uses(std::slice::from_ref(&path)) {
+                continue;
+            }
+            let volume = entry.volume.clamp(0.0, 1.0);
+            if !device_available() {
+                self.sounds.insert(
+                    id.clone(),
+                    GameSound {
+                        sound: None,
+                        volume,
+                    },
+                );
+                continue;
+            }
+            let sound = match report.check(loading_sound(assets, &path).await) {
+                Some(sound) => Some(sound),
+                None if self.sounds.contains_key(id) => continue,
+                None => Some(silent_sound().await),
+            };
+            self.sounds.insert(id.clone(), GameSound { sound, volume });
+        }
+
+        for (id, entry) in &self.manifest.fonts {
+            let path = asset_path(&entry.file);
+            if uses(std::slice::from_ref(&path)) {
+                if let Some(font) = report.check(load_font(assets, &path).await) {
+                    self.fonts.insert(id.clone(), font);
+                    reloaded.ui = true;
+                }
+            }
+        }
+        for (id, entry) in &self.manifest.images {
+            let path = asset_path(&entry.file);
+            if uses(std::slice::from_ref(&path)) {
+                if let Some(image) = report.check(load_ui_image(assets, &path).await) {
+                    self.images.insert(id.clone(), image);
+                    reloaded.ui = true;
+                }
+            }
+        }
+        reloaded
+    }
+
+    pub fn ui(&self) -> &UiEntry {
+        &self.manifest.ui
+    }
+
+    /// Model with the id, the required ids always exist.
+    pub fn model(&self, id: &str) -> Rc<Model3D> {
+        self.models[id].clone()
+    }
+
+    /// The model `id` and every model with an id starting with `id.`,
+    /// different looks of the same thing.
+    pub fn model_variants(&self, id: &str) -> Vec<Rc<Model3D>> {
+        let prefix = format!("{}.", id);
+        self.models
+            .iter()
+            .filter(|(name, _)| *name == id || name.starts_with(&prefix))
+            .map(|(_, model)| model.clone())
+            .collect()
+    }
+
+    /// Skins sorted by the score that unlocks them, only the classic snake
+    /// if the manifest has none.
+    pub fn skins(&self) -> Vec<(String, SkinEntry)> {
+        let mut skins: Vec<_> = self
+            .manifest
+            .skins
+            .iter()
+            .map(|(id, skin)| (id.clone(), skin.clone()))
+            .collect();
+        if skins.is_empty() {
+            skins.push((String::from("classic"), SkinEntry::default()));
+        }
+        skins.sort_by_key(|(_, skin)| skin.unlock_score);
+        skins
+    }
+
+    pub fn achievements(&self) -> &BTreeMap<String, AchievementEntry> {
+        &self.manifest.achievements
+    }
+
+    /// Head and body model of a skin, models that don't exist are replaced by the default ones.
+    pub fn skin_models(&self, skin: &SkinEntry) -> (Rc<Model3D>, Rc<Model3D>) {
+        let model = |id: &str, default: &str| {
+            self.models
+                .get(id)
+                .cloned()
+                .unwrap_or_else(|| self.model(default))
+        };
+        (
+            model(&skin.head, "snake.head"),
+            model(&skin.body, "snake.body"),
+        )
+    }
+
+    /// Sound with the id, the required ids always exist.
+    pub fn sound(&self, id: &str) -> &GameSound {
+        &self.sounds[id]
+    }
+
+    /// `None` if the font failed to load, the default font is used then.
+    pub fn font(&self, id: &str) -> Option<&[u8]> {
+        self.fonts.get(id).map(Vec::as_slice)
+    }
+
+    pub fn image(&self, id: &str) -> Image {
+        self.images
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| plain_image(BLACK))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_built_in_manifest() {
+        let manifest = Manifest::parse("built-in", BUILT_IN).unwrap();
+        for id in AssetLibrary::REQUIRED_MODELS {
+            assert!(manifest.models.contains_key(id), "{} missing", id);
+        }
+        for id in AssetLibrary::REQUIRED_SOUNDS {
+            assert!(manifest.sounds.contains_key(id), "{} missing", id);
+        }
+        assert_eq!(manifest.models["snake.head"].scale, 20.0);
+        assert_eq!(manifest.sounds["sfx.crash"].volume, 0.01);
+        assert!(manifest.fonts.contains_key("ui.font"));
+        for skin in manifest.skins.values() {
+            assert!(
+                manifest.models.contains_key(&skin.head),
+                "{} missing",
+                skin.head
+            );
+            assert!(
+                manifest.models.contains_key(&skin.body),
+                "{} missing",
+                skin.body
+            );
+        }
+    }
+
+    #[test]
+    fn test_skin_defaults() {
+        let manifest = Manifest::parse(
+            "test",
+            "[skins.gold]\nname = \"Gold\"\ntint = [255, 215, 0]\nunlock_score = 20\n",
+        )
+        .unwrap();
+        let skin = &manifest.skins["gold"];
+        assert_eq!(skin.head, "snake.head");
+        assert_eq!(skin.tint_color(), Color::from_rgba(255, 215, 0, 255));
+        assert_eq!(skin.unlock_score, 20);
+    }
+
+    #[test]
+    fn test_achievement() {
+        let manifest = Manifest::parse(
+            "test",
+            "[achievements.long]\nname = \"Long\"\ndescription = \"Reach length 50\"\nstat = \"length\"\ngoal = 50\n",
+        )
+        .unwrap();
+        assert_eq!(manifest.achievements["long"].stat, Stat::Length);
+        let error = Manifest::parse(
+            "test",
+            "[achievements.x]\nname = \"X\"\ndescription = \"\"\nstat = \"lenght\"\ngoal = 1\n",
+        );
+        assert!(matches!(error, Err(AssetError::Manifest { .. })));
+    }
+
+    #[test]
+    fn test_defaults() {
+        let manifest = Manifest::parse(
+            "test",
+            r#"
+            [models."food.normal.pear"]
+            file = "pear/pear.glb"
+            rotation = [0, 90, 0]
+            filter = "linear"
+            "#,
+        )
+        .unwrap();
+        let entry = &manifest.models["food.normal.pear"];
+        assert_eq!(entry.scale, 1.0);
+        assert_eq!(entry.filter, Filter::Linear);
+        let options = entry.options();
+        // Turned by 90 degrees around Y, +Z becomes +X
+        let turned = options.rotation * macroquad::math::Vec3::Z;
+        assert!((turned - macroquad::math::Vec3::X).length() < 1e-5);
+        assert_eq!(manifest.ui.button_font_size, 64);
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        let error = Manifest::parse("test", "[models.\"a\"]\nfile = \"a.obj\"\nscal = 2.0\n");
+        assert!(matches!(error, Err(AssetError::Manifest { .. })));
+    }
+}
@@ -1,14 +1,24 @@
-use crate::food::FoodFactory;
-use crate::movement::View;
-use crate::score::Score;
+use crate::achievements::AchievementProgress;
+use crate::assets::LoadReport;
+use crate::draw_utils::BACKGROUND_COLOR;
+use crate::events::Events;
+use crate::food::{FoodFactory, FoodTarget, FoodVariant};
+use crate::leaderboard::{Leaderboard, Tab};
+use crate::movement::{CameraMode, View};
+use crate::profiles::{NameEntry, ProfileData, Profiles};
+use crate::score::{format_date, Score};
+use crate::skins::{is_unlocked, SkinChoice, SkinMenu};
 use crate::snake::Shnek;
-use crate::GameState;
-
-use crate::models3d::Model3D;
-use macroquad::audio::Sound;
-use macroquad::audio::{play_sound, PlaySoundParams};
+use crate::state::{GameState, StateMachine, Transition};
+use crate::summary::{format_time, RunSummary};
+use crate::toasts::Toasts;
+
+use crate::audio::{Audio, GameSound};
+use crate::manifest::AssetLibrary;
+use crate::models3d::DrawStats;
+use macroquad::hash;
 use macroquad::prelude::*;
-use macroquad::{hash, ui::root_ui};
+use macroquad::ui::{root_ui, widgets, Ui};
 
 fn menu_window() -> (Vec2, Vec2) {
     draw_rectangle(
@@ -20,7 +30,7 @@ fn menu_window() -> (Vec2, Vec2) {
         color_u8!(0, 0, 0, 128),
     );
 
-    let window_size = vec2(400.0, 500.0);
+    let window_size = vec2(400.0, 720.0);
     let window_pos = vec2(
         screen_width() / 2.0 - window_size.x / 2.0,
         screen_height() / 2.0 - window_size.y / 2.0,
@@ -28,34 +38,38 @@ fn menu_window() -> (Vec2, Vec2) {
     (window_pos, window_size)
 }
 
-pub fn main_menu(game_state: &mut GameState, click_sound: &Sound, score_file: &mut Score) {
-    if *game_state == GameState::MainMenu {
+/// `player` is the name of the current profile.
+pub fn main_menu(states: &mut StateMachine, audio: &Audio, click_sound: &GameSound, player: &str) {
+    if states.current() == GameState::MainMenu {
         let (window_pos, window_size) = menu_window();
         let main_menu_id = hash!();
         root_ui().window(main_menu_id, window_pos, window_size, |ui| {
-            ui.label(vec2(90.0, 0.0), "Main Menu");
+            ui.label(vec2(20.0, 0.0), &format!("Playing as {}", player));
             if ui.button(vec2(70.0, 50.0), "Play") {
-                play_sound(
-                    click_sound,
-                    PlaySoundParams {
-                        looped: false,
-                        volume: 0.1,
-                    },
-                );
-                *game_state = GameState::Running;
+                audio.play(click_sound);
+                states.request(Transition::Push(GameState::Running));
             }
-            if ui.button(vec2(45.0, 150.0), "Score") {
-                play_sound(
-                    click_sound,
-                    PlaySoundParams {
-                        looped: false,
-                        volume: 0.1,
-                    },
-                );
-                score_file.prev_game_state = *game_state;
-                *game_state = GameState::Score;
+            if ui.button(vec2(30.0, 140.0), "Player") {
+                audio.play(click_sound);
+                states.request(Transition::Push(GameState::Profiles));
             }
-            if ui.button(vec2(70.0, 250.0), "Quit") {
+            if ui.button(vec2(45.0, 230.0), "Score") {
+                audio.play(click_sound);
+                states.request(Transition::Push(GameState::Score));
+            }
+            if ui.button(vec2(55.0, 320.0), "Skins") {
+                audio.play(click_sound);
+                states.request(Transition::Push(GameState::Skins));
+            }
+            if ui.button(vec2(55.0, 410.0), "Stats") {
+                audio.play(click_sound);
+                states.request(Transition::Push(GameState::Stats));
+            }
+            if ui.button(vec2(30.0, 500.0), "Awards") {
+                audio.play(click_sound);
+                states.request(Transition::Push(GameState::Achievements));
+            }
+            if ui.button(vec2(70.0, 590.0), "Quit") {
                 std::process::exit(0);
             }
         });
@@ -63,152 +77,529 @@ pub fn main_menu(game_state: &mut GameState, click_sound: &Sound, score_file: &m
     }
 }
 
-pub fn paused<'a>(
-    game_state: &mut GameState,
-    click: &Sound,
-    high_score: &mut i32,
-    player: &mut Shnek,
-    view: &mut View,
-    food_factory: &mut FoodFactory<'a>,
-    good_food_model: &'a Model3D,
-    bad_food_model: &'a Model3D,
-    poop_model: &'a Model3D,
+pub fn paused(
+    states: &mut StateMachine,
+    audio: &mut Audio,
+    click: &GameSound,
+    high_score: i32,
     score_file: &mut Score,
+    player: &str,
+    summary: &RunSummary,
 ) {
-    if *game_state == GameState::Paused || *game_state == GameState::GameOver {
-        if *game_state == GameState::GameOver {
-            score_file.write(*high_score as usize);
-        }
-
+    if states.current() == GameState::Paused {
         let (window_pos, window_size) = menu_window();
         let menu_id = hash!();
         root_ui().window(menu_id, window_pos, window_size, |ui| {
-            if *game_state == GameState::Paused {
-                ui.label(vec2(115.0, 0.0), "Paused")
-            } else {
-                ui.label(vec2(95.0, 0.0), "Game over")
+            ui.label(vec2(115.0, 0.0), "Paused");
+            if ui.button(vec2(27.0, 50.0), "Resume") {
+                audio.play(click);
+                states.request(Transition::Pop);
             }
-
-            if *game_state == GameState::Paused && ui.button(vec2(27.0, 50.0), "Resume") {
-                play_sound(
-                    click,
-                    PlaySoundParams {
-                        looped: false,
-                        volume: 0.1,
-                    },
-                );
-                *game_state = GameState::Running;
-            } else if *game_state == GameState::GameOver && ui.button(vec2(45.0, 50.0), "Score") {
-                play_sound(
-                    click,
-                    PlaySoundParams {
-                        looped: false,
-                        volume: 0.1,
-                    },
-                );
-                score_file.prev_game_state = *game_state;
-                *game_state = GameState::Score;
-            }
-
             if ui.button(vec2(50.0, 150.0), "Reset") {
-                play_sound(
-                    click,
-                    PlaySoundParams {
-                        looped: false,
-                        volume: 0.1,
-                    },
-                );
-                *high_score = 0;
-                player.reset();
-                view.reset();
-                *food_factory = FoodFactory::new(good_food_model, bad_food_model, poop_model);
-                score_file.reset();
-                for _ in 0..player.start_length {
-                    player.add_segment();
-                }
-                *game_state = GameState::Running;
+                audio.play(click);
+                states.request(Transition::Restart(GameState::Running));
             }
             if ui.button(vec2(70.0, 250.0), "Quit") {
-                score_file.write(*high_score as usize);
+                if let Err(error) = score_file.write(high_score as usize, player, summary) {
+                    eprintln!("failed to save the score: {}", error);
+                }
                 std::process::exit(0);
             }
+            sound_settings(ui, audio, vec2(10.0, 360.0));
         });
         root_ui().move_window(menu_id, window_pos);
     }
 }
 
-pub fn score_menu(
-    game_state: &mut GameState,
-    click: &Sound,
-    high_score: i32,
-    score_file: &mut Score,
+/// The score was already written when the game ended.
+pub fn game_over(
+    states: &mut StateMachine,
+    audio: &mut Audio,
+    click: &GameSound,
+    summary: &RunSummary,
+    next_seed: &mut Option<u64>,
 ) {
-    if *game_state == GameState::Score {
-        let window_size = vec2(250., 100.);
-        let window_pos = vec2(
-            screen_width() - window_size.x,
-            screen_height() - window_size.y,
-        );
+    if states.current() == GameState::GameOver {
+        let (window_pos, window_size) = menu_window();
         let menu_id = hash!();
-
         root_ui().window(menu_id, window_pos, window_size, |ui| {
-            if ui.button(vec2(-15., -30.), "Back") {
-                play_sound(
-                    click,
-                    PlaySoundParams {
-                        looped: false,
-                        volume: 0.1,
-                    },
-                );
-                *game_state = score_file.prev_game_state
+            ui.label(vec2(95.0, 0.0), "Game over");
+            if ui.button(vec2(45.0, 50.0), "Score") {
+                audio.play(click);
+                states.request(Transition::Push(GameState::Score));
+            }
+            if ui.button(vec2(50.0, 150.0), "Reset") {
+                audio.play(click);
+                states.request(Transition::Restart(GameState::Running));
             }
+            if ui.button(vec2(70.0, 250.0), "Quit") {
+                std::process::exit(0);
+            }
+            sound_settings(ui, audio, vec2(10.0, 360.0));
         });
         root_ui().move_window(menu_id, window_pos);
 
-        score_file.write(high_score as usize);
+        // Next to the menu
+        let summary_pos = window_pos - vec2(window_size.x + 20.0, 0.0);
+        let summary_id = hash!();
+        root_ui().window(summary_id, summary_pos, window_size, |ui| {
+            let cause = summary
+                .cause
+                .map_or("Game over", |cause| cause.description());
+            let mut lines = vec![
+                cause.to_string(),
+                format!("length: {}", summary.final_length),
+                format!("max length: {}", summary.max_length),
+            ];
+            for variant in FoodVariant::ALL {
+                lines.push(format!(
+                    "{} eaten: {}",
+                    variant.name(),
+                    summary.eaten(variant)
+                ));
+            }
+            lines.push(format!("distance: {}", summary.distance.round()));
+            lines.push(format!("time alive: {}", format_time(summary.time_alive)));
+            lines.push(format!("boost time: {}", format_time(summary.boost_time)));
+            lines.push(format!("seed: {}", summary.seed));
+            for (i, line) in lines.iter().enumerate() {
+                ui.label(vec2(10.0, i as f32 * 34.0), line);
+            }
+            if ui.button(vec2(10.0, 380.0), "Retry seed") {
+                audio.play(click);
+                *next_seed = Some(summary.seed);
+                states.request(Transition::Restart(GameState::Running));
+            }
+        });
+        root_ui().move_window(summary_id, summary_pos);
+    }
+}
 
-        draw_rectangle(0.0, 0.0, screen_width(), screen_height(), BLACK);
+/// Volume sliders and mute, changes are saved by the main loop.
+fn sound_settings(ui: &mut Ui, audio: &mut Audio, position: Vec2) {
+    let settings = &mut audio.settings;
+    widgets::Group::new(hash!(), vec2(370.0, 200.0))
+        .position(position)
+        .ui(ui, |ui| {
+            ui.slider(hash!(), "volume", 0.0..1.0, &mut settings.master);
+            ui.slider(hash!(), "music", 0.0..1.0, &mut settings.music);
+            ui.slider(hash!(), "effects", 0.0..1.0, &mut settings.sfx);
+            ui.checkbox(hash!(), "mute (M)", &mut settings.muted);
+            ui.checkbox(hash!(), "food sounds (H)", &mut settings.food_hint);
+        });
+}
 
-        draw_rectangle(0.0, 0.0, screen_width(), screen_height(), BLACK);
+/// The best games on pages, with tabs for today and this session and a button
+/// for the game mode once there is more than one. The last game is highlighted.
+pub fn score_menu(
+    states: &mut StateMachine,
+    audio: &Audio,
+    click: &GameSound,
+    score_file: &Score,
+    leaderboard: &mut Leaderboard,
+) {
+    if states.current() != GameState::Score {
+        return;
+    }
+    draw_rectangle(0.0, 0.0, screen_width(), screen_height(), BLACK);
+
+    let tabs_id = hash!();
+    let tabs_pos = vec2(20.0, 20.0);
+    root_ui().window(tabs_id, tabs_pos, vec2(1000.0, 100.0), |ui| {
+        let mut x = 10.0;
+        for tab in Tab::ALL {
+            if ui.button(vec2(x, 0.0), tab.name()) {
+                audio.play(click);
+                leaderboard.set_tab(tab);
+            }
+            x += 60.0 + tab.name().len() as f32 * 32.0;
+        }
+        if leaderboard.has_modes() {
+            let mode = leaderboard.mode.as_deref().unwrap_or("all modes");
+            if ui.button(vec2(x + 40.0, 0.0), mode) {
+                audio.play(click);
+                leaderboard.next_mode();
+            }
+        }
+    });
+    root_ui().move_window(tabs_id, tabs_pos);
 
-        let contents = &score_file.read();
+    let (_, wheel) = mouse_wheel();
+    if wheel != 0.0 {
+        leaderboard.turn(-(wheel.signum() as i32));
+    }
 
-        draw_multiline_text(
-            &format!("scores:\n{}", *contents),
-            10.0,
-            50.0,
-            100.0,
-            None,
-            WHITE,
+    let columns = [40.0, 120.0, 460.0, 620.0, 780.0, 920.0];
+    let header = ["#", "name", "score", "length", "time", "date"];
+    let top = 180.0;
+    let row_height = 44.0;
+    let title = match &leaderboard.mode {
+        Some(mode) => format!("Best games: {} - {}", leaderboard.tab.name(), mode),
+        None => format!("Best games: {}", leaderboard.tab.name()),
+    };
+    draw_text(&title, columns[0], top - 20.0, 36.0, WHITE);
+    for (x, text) in columns.iter().zip(header) {
+        draw_text(text, *x, top + row_height, 36.0, GRAY);
+    }
+    let (first_rank, page) = leaderboard.page();
+    if page.is_empty() {
+        draw_text(
+            "no games yet",
+            columns[0],
+            top + 2.0 * row_height,
+            36.0,
+            GRAY,
         );
+    }
+    for (i, entry) in page.iter().enumerate() {
+        let rank = first_rank + i;
+        let y = top + (i + 2) as f32 * row_height;
+        if score_file.last.as_ref() == Some(entry) {
+            draw_rectangle(
+                columns[0] - 10.0,
+                y - row_height + 10.0,
+                1100.0,
+                row_height,
+                DARKBLUE,
+            );
+        }
+        let color = if rank == 1 { GOLD } else { WHITE };
+        let name = if entry.name.is_empty() {
+            "-"
+        } else {
+            &entry.name
+        };
+        let unknown = |value: String, known: bool| if known { value } else { String::from("-") };
+        let cells = [
+            rank.to_string(),
+            name.to_string(),
+            entry.score.to_string(),
+            unknown(entry.length.to_string(), entry.length > 0),
+            unknown(format_time(entry.duration as f32), entry.duration > 0),
+            format_date(entry.time),
+        ];
+        for (x, text) in columns.iter().zip(cells) {
+            draw_text(&text, *x, y, 36.0, color);
+        }
+    }
 
-        // Collect into a Vec<i32> (ignoring empty lines)
-        let mut numbers: Vec<i32> = contents
-            .lines()
-            .filter_map(|line| line.trim().parse::<i32>().ok())
-            .collect();
-
-        // Sort descending
-        numbers.sort_by(|a, b| b.cmp(a));
-
-        // Take top 3
-        let top3: Vec<i32> = numbers.into_iter().take(3).collect();
-
-        let best = top3
-            .iter()
-            .map(|n| n.to_string())
-            .collect::<Vec<String>>()
-            .join("\n");
-
-        draw_multiline_text(
-            &format!("best: \n{}", best),
-            screen_width() - 250.,
-            50.,
-            100.,
-            None,
-            GOLD,
+    let pages_id = hash!();
+    let pages_size = vec2(700.0, 100.0);
+    let pages_pos = vec2(20.0, top + (Leaderboard::ROWS + 2) as f32 * row_height);
+    root_ui().window(pages_id, pages_pos, pages_size, |ui| {
+        if ui.button(vec2(10.0, 0.0), "<") {
+            audio.play(click);
+            leaderboard.turn(-1);
+        }
+        ui.label(
+            vec2(110.0, 15.0),
+            &format!("page {}/{}", leaderboard.page + 1, leaderboard.page_count()),
         );
+        if ui.button(vec2(300.0, 0.0), ">") {
+            audio.play(click);
+            leaderboard.turn(1);
+        }
+        if ui.button(vec2(450.0, 0.0), "Back") {
+            audio.play(click);
+            states.request(Transition::Pop);
+        }
+    });
+    root_ui().move_window(pages_id, pages_pos);
+}
+
+/// Picking a skin, with a turning preview of the snake.
+#[allow(clippy::too_many_arguments)]
+pub fn skins_menu(
+    states: &mut StateMachine,
+    audio: &Audio,
+    click: &GameSound,
+    menu: &mut SkinMenu,
+    choice: &mut SkinChoice,
+    player: &mut Shnek,
+    library: &AssetLibrary,
+    best_score: i32,
+    dt: f32,
+    toasts: &mut Toasts,
+) {
+    if states.current() != GameState::Skins {
+        return;
     }
+    let skins = library.skins();
+    // The manifest can change while the menu is open
+    let shown = match menu.shown {
+        Some(shown) if shown < skins.len() => shown,
+        _ => {
+            let shown = choice.index(&skins, best_score);
+            menu.show(shown);
+            shown
+        }
+    };
+    let (id, skin) = &skins[shown];
+
+    clear_background(BACKGROUND_COLOR);
+    menu.preview(library, skin).draw(dt);
+
+    let window_size = vec2(700.0, 260.0);
+    let window_pos = vec2(
+        screen_width() / 2.0 - window_size.x / 2.0,
+        screen_height() - window_size.y - 40.0,
+    );
+    let menu_id = hash!();
+    root_ui().window(menu_id, window_pos, window_size, |ui| {
+        let status = if *id == choice.id {
+            String::from("(current)")
+        } else if !is_unlocked(skin, best_score) {
+            format!("(score {} to unlock)", skin.unlock_score)
+        } else {
+            String::new()
+        };
+        ui.label(vec2(20.0, 0.0), &format!("{} {}", skin.name, status));
+        if ui.button(vec2(20.0, 50.0), "<") {
+            audio.play(click);
+            menu.show((shown + skins.len() - 1) % skins.len());
+        }
+        if ui.button(vec2(600.0, 50.0), ">") {
+            audio.play(click);
+            menu.show((shown + 1) % skins.len());
+        }
+        if is_unlocked(skin, best_score) && ui.button(vec2(250.0, 50.0), "Pick") {
+            audio.play(click);
+            choice.id = id.clone();
+            toasts.report_save_error("skin", choice.save());
+            choice.apply(player, library, best_score);
+        }
+        if ui.button(vec2(250.0, 150.0), "Back") {
+            audio.play(click);
+            states.request(Transition::Pop);
+        }
+    });
+    root_ui().move_window(menu_id, window_pos);
+}
+
+/// Lifetime statistics, `message` tells where the exports went.
+pub fn stats_menu(
+    states: &mut StateMachine,
+    audio: &Audio,
+    click: &GameSound,
+    profile: &ProfileData,
+    profile_name: &str,
+    message: &mut Option<String>,
+) {
+    if states.current() != GameState::Stats {
+        return;
+    }
+    draw_rectangle(0.0, 0.0, screen_width(), screen_height(), BLACK);
+    let window_size = vec2(700.0, 640.0);
+    let window_pos = vec2(
+        screen_width() / 2.0 - window_size.x / 2.0,
+        screen_height() / 2.0 - window_size.y / 2.0,
+    );
+    let menu_id = hash!();
+    root_ui().window(menu_id, window_pos, window_size, |ui| {
+        ui.label(vec2(20.0, 0.0), "Stats");
+        for (i, (name, value)) in profile.stats.rows().iter().enumerate() {
+            let y = 45.0 + i as f32 * 32.0;
+            ui.label(vec2(20.0, y), name);
+            ui.label(vec2(450.0, y), value);
+        }
+        if ui.button(vec2(20.0, 420.0), "Export") {
+            audio.play(click);
+            *message = Some(match profile.stats.export(&profile.dir, profile_name) {
+                Ok(()) => format!("saved to {}", profile.dir.display()),
+                Err(error) => format!("export failed: {}", error),
+            });
+        }
+        if ui.button(vec2(450.0, 420.0), "Back") {
+            audio.play(click);
+            states.request(Transition::Pop);
+        }
+        if let Some(message) = message {
+            ui.label(vec2(20.0, 530.0), message);
+        }
+    });
+    root_ui().move_window(menu_id, window_pos);
+}
+
+/// All achievements with how far the player got.
+pub fn achievements_menu(
+    states: &mut StateMachine,
+    audio: &Audio,
+    click: &GameSound,
+    library: &AssetLibrary,
+    progress: &AchievementProgress,
+) {
+    if states.current() != GameState::Achievements {
+        return;
+    }
+    draw_rectangle(0.0, 0.0, screen_width(), screen_height(), BLACK);
+    let achievements = library.achievements();
+    let window_size = vec2(1000.0, 180.0 + achievements.len() as f32 * 70.0);
+    let window_pos = vec2(
+        screen_width() / 2.0 - window_size.x / 2.0,
+        (screen_height() / 2.0 - window_size.y / 2.0).max(0.0),
+    );
+    let menu_id = hash!();
+    root_ui().window(menu_id, window_pos, window_size, |ui| {
+        ui.label(vec2(20.0, 0.0), "Achievements");
+        for (i, (id, achievement)) in achievements.iter().enumerate() {
+            let y = 50.0 + i as f32 * 70.0;
+            let status = if progress.is_unlocked(id, achievement) {
+                String::from("unlocked")
+            } else {
+                format!("{} / {}", progress.value(id).floor(), achievement.goal)
+            };
+            ui.label(vec2(20.0, y), &achievement.name);
+            ui.label(vec2(750.0, y), &status);
+            ui.label(vec2(40.0, y + 30.0), &achievement.description);
+        }
+        let y = 60.0 + achievements.len() as f32 * 70.0;
+        if ui.button(vec2(20.0, y), "Back") {
+            audio.play(click);
+            states.request(Transition::Pop);
+        }
+    });
+    root_ui().move_window(menu_id, window_pos);
+}
+
+/// Switching between the players, `shown` is the profile shown with `<` and `>`.
+pub fn profiles_menu(
+    states: &mut StateMachine,
+    audio: &Audio,
+    click: &GameSound,
+    profiles: &mut Profiles,
+    shown: &mut usize,
+    toasts: &mut Toasts,
+) {
+    if states.current() != GameState::Profiles {
+        return;
+    }
+    let count = profiles.names.len();
+    *shown = (*shown).min(count - 1);
+    let name = profiles.names[*shown].clone();
+    let (window_pos, window_size) = text_window();
+    let menu_id = hash!();
+    root_ui().window(menu_id, window_pos, window_size, |ui| {
+        let status = if name == profiles.current {
+            " (current)"
+        } else {
+            ""
+        };
+        ui.label(vec2(20.0, 0.0), &format!("{}{}", name, status));
+        if ui.button(vec2(20.0, 50.0), "<") {
+            audio.play(click);
+            *shown = (*shown + count - 1) % count;
+        }
+        if ui.button(vec2(600.0, 50.0), ">") {
+            audio.play(click);
+            *shown = (*shown + 1) % count;
+        }
+        if name != profiles.current && ui.button(vec2(270.0, 50.0), "Use") {
+            audio.play(click);
+            profiles.current = name.clone();
+            toasts.report_save_error("profiles", profiles.save());
+        }
+        if ui.button(vec2(20.0, 150.0), "New") {
+            audio.play(click);
+            states.request(Transition::Push(GameState::NewProfile));
+        }
+        if ui.button(vec2(450.0, 150.0), "Back") {
+            audio.play(click);
+            states.request(Transition::Pop);
+        }
+    });
+    root_ui().move_window(menu_id, window_pos);
+}
+
+/// Typing the name of a new profile, which is used right away.
+pub fn new_profile_menu(
+    states: &mut StateMachine,
+    audio: &Audio,
+    click: &GameSound,
+    profiles: &mut Profiles,
+    entry: &mut NameEntry,
+    toasts: &mut Toasts,
+) {
+    if states.current() != GameState::NewProfile {
+        return;
+    }
+    let mut done = entry.update();
+    let (window_pos, window_size) = text_window();
+    let menu_id = hash!();
+    root_ui().window(menu_id, window_pos, window_size, |ui| {
+        ui.label(vec2(20.0, 0.0), "Name of the new player:");
+        ui.label(vec2(20.0, 40.0), &format!("{}_", entry.text));
+        if let Err(problem) = profiles.check_name(&entry.text) {
+            ui.label(vec2(300.0, 40.0), problem);
+        }
+        if ui.button(vec2(20.0, 150.0), "OK") {
+            audio.play(click);
+            done = true;
+        }
+        if ui.button(vec2(450.0, 150.0), "Back") {
+            audio.play(click);
+            states.request(Transition::Pop);
+        }
+    });
+    root_ui().move_window(menu_id, window_pos);
+    if done && profiles.add(&entry.text).is_ok() {
+        toasts.report_save_error("profiles", profiles.save());
+        states.request(Transition::Pop);
+    }
+    if is_key_pressed(KeyCode::Escape) {
+        states.request(Transition::Pop);
+    }
+}
+
+/// Asks for a name when the game made it onto the score screen, `player` is
+/// used if the name is left empty.
+#[allow(clippy::too_many_arguments)]
+pub fn name_entry_menu(
+    states: &mut StateMachine,
+    audio: &Audio,
+    click: &GameSound,
+    entry: &mut NameEntry,
+    score_file: &mut Score,
+    high_score: i32,
+    player: &str,
+    summary: &RunSummary,
+    toasts: &mut Toasts,
+) {
+    if states.current() != GameState::NameEntry {
+        return;
+    }
+    let mut done = entry.update() || is_key_pressed(KeyCode::Escape);
+    let (window_pos, window_size) = text_window();
+    let menu_id = hash!();
+    root_ui().window(menu_id, window_pos, window_size, |ui| {
+        ui.label(
+            vec2(20.0, 0.0),
+            &format!("High score {}! Your name:", high_score),
+        );
+        ui.label(vec2(20.0, 40.0), &format!("{}_", entry.text));
+        if ui.button(vec2(20.0, 150.0), "OK") {
+            audio.play(click);
+            done = true;
+        }
+    });
+    root_ui().move_window(menu_id, window_pos);
+    if done {
+        let name = match entry.text.trim() {
+            "" => player,
+            name => name,
+        };
+        let written = score_file.write(high_score as usize, name, summary);
+        toasts.report_save_error("the score", written);
+        states.request(Transition::Pop);
+    }
+}
+
+/// A wide window in the middle of the screen, for the profile and name screens.
+fn text_window() -> (Vec2, Vec2) {
+    // Darkens the screen and starts at the same height as the other menus
+    let (menu_pos, _) = menu_window();
+    let window_size = vec2(700.0, 260.0);
+    let window_pos = vec2(screen_width() / 2.0 - window_size.x / 2.0, menu_pos.y);
+    (window_pos, window_size)
 }
 
 pub struct FPSCounter {
@@ -235,105 +626,148 @@ impl FPSCounter {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn draw_status(
     score: i32,
     high_score: i32,
-    food_distance: f32,
+    nearest_food: Option<FoodTarget>,
     food_count: usize,
     max_food: usize,
     fps_counter: &FPSCounter,
+    camera_mode: CameraMode,
+    draw_stats: DrawStats,
 ) {
     draw_text(
-        &format!("fps: {}", fps_counter.fps().round()),
+        format!("fps: {}", fps_counter.fps().round()),
         10.0,
         20.0,
         30.0,
         BLACK,
     );
 
-    draw_text(&format!("score: {}", score), 10.0, 50.0, 30.0, BLACK);
+    draw_text(format!("score: {}", score), 10.0, 50.0, 30.0, BLACK);
     draw_text(
-        &format!("high score: {}", high_score),
+        format!("high score: {}", high_score),
         10.0,
         70.0,
         30.0,
         BLACK,
     );
     draw_text(
-        &format!("food distance: {}", food_distance.round()),
+        match nearest_food {
+            Some(food) => format!("food distance: {}", food.distance().round()),
+            None => String::from("food distance: -"),
+        },
         10.0,
         100.0,
         30.0,
         BLACK,
     );
     draw_text(
-        &format!("food count: {}", food_count),
+        format!("food count: {}", food_count),
         10.0,
         130.0,
         30.0,
         BLACK,
     );
-    draw_text(&format!("max food: {}", max_food), 10.0, 150.0, 30.0, BLACK);
+    draw_text(format!("max food: {}", max_food), 10.0, 150.0, 30.0, BLACK);
+    draw_text(
+        format!("camera: {}", camera_mode.name()),
+        10.0,
+        180.0,
+        30.0,
+        BLACK,
+    );
+    draw_text(
+        format!(
+            "meshes drawn: {} (culled {})",
+            draw_stats.meshes_drawn, draw_stats.culled
+        ),
+        10.0,
+        210.0,
+        30.0,
+        BLACK,
+    );
+    draw_text(
+        format!("vertices: {}", draw_stats.vertices),
+        10.0,
+        230.0,
+        30.0,
+        BLACK,
+    );
 }
 
-pub fn running<'a>(
-    game_state: &mut GameState,
-    eat_sound: &Sound,
-    collision_sound: &Sound,
+/// Moves the snake and eats, what happened is pushed to `events`.
+pub fn running(
+    states: &mut StateMachine,
     player: &mut Shnek,
     view: &mut View,
-    food_factory: &mut FoodFactory<'a>,
+    food_factory: &mut FoodFactory,
+    events: &mut Events,
     dt: f32,
-    food_distance: &mut f32,
+    nearest_food: &mut Option<FoodTarget>,
 ) {
-    if *game_state == GameState::Running {
+    if states.current() == GameState::Running {
         // Only update if not paused
         view.rotate(dt);
 
-        player.set_direction(view.forward(), view.up());
-        player.move_forward(dt);
+        player.set_orientation(view.orientation());
+        player.move_forward(dt, events);
 
-        player.check_boost_and_move(dt);
+        player.check_boost_and_move(dt, events);
+        player.check_boost_time(food_factory, player.start_length, events);
+        player.check_tail_collision(events);
+        *nearest_food = food_factory.check_food_collision(player, events);
 
-        if player.check_boost_time(food_factory, player.start_length) {
-            *game_state = GameState::GameOver;
-        }
-
-        if player.check_tail_collision() {
-            play_sound(
-                collision_sound,
-                PlaySoundParams {
-                    looped: false,
-                    volume: 0.01,
-                },
-            );
-            *game_state = GameState::GameOver;
-        }
         if !player.alive {
-            *game_state = GameState::GameOver;
-        }
-        let eaten: bool;
-        (*food_distance, eaten) = food_factory.check_food_collision(player);
-        if eaten {
-            play_sound(
-                eat_sound,
-                PlaySoundParams {
-                    looped: false,
-                    volume: 0.1,
-                },
-            )
+            states.request(Transition::Replace(GameState::GameOver));
         }
     }
 }
 
 pub fn help() {
+    let text = "W A S D - change direction   Q E - spin   Left Shift - speed boost   \
+        Esc/Space - pause   C - camera   V - orbit when paused   R - radar   L - lighting   F - fog   K - culling   M - mute   H - food sounds";
     let y = screen_height() - 10.;
-    let x = screen_width() / 2.0 - 390.;
-    draw_text(
-        "W A S D - change direction   Q E - spin   Left Shift - speed boost   Esc/Space - pause",
-        x,
-        y,
-        20.0,
-        BLACK,
-    );
+    let x = (screen_width() - measure_text(text, None, 20, 1.0).width) / 2.0;
+    draw_text(text, x, y, 20.0, BLACK);
+}
+
+/** Lists the assets that failed to load, before the game starts.
+Returns false if the player wants to quit instead of playing with placeholders.
+Uses the default font, the game font may be one of the failures.
+*/
+pub async fn load_errors(report: &LoadReport) -> bool {
+    loop {
+        if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::Space) {
+            return true;
+        }
+        if is_key_pressed(KeyCode::Escape) {
+            return false;
+        }
+        clear_background(BLACK);
+        let mut y = 60.0;
+        draw_text("Some assets failed to load", 40.0, y, 40.0, RED);
+        y += 50.0;
+        for failure in &report.failures {
+            draw_text(failure.to_string(), 40.0, y, 22.0, WHITE);
+            y += 28.0;
+        }
+        y += 30.0;
+        draw_text(
+            "Missing models and textures are replaced by checkerboards, missing sounds are silent.",
+            40.0,
+            y,
+            22.0,
+            LIGHTGRAY,
+        );
+        draw_text(
+            "Enter - play anyway   Esc - quit",
+            40.0,
+            y + 40.0,
+            28.0,
+            YELLOW,
+        );
+        next_frame().await;
+    }
 }
@@ -0,0 +1,139 @@
+use crate::draw_utils::SPACE_SIZE;
+use macroquad::camera::Camera3D;
+use macroquad::math::Vec3;
+use macroquad::window::{screen_height, screen_width};
+
+/** Decides which parts of the repeated world are worth drawing.
+The view frustum is approximated with a cone around the view direction,
+which is a bit generous in the corners, but cheap to test against spheres.
+*/
+#[derive(Debug, Copy, Clone)]
+pub struct Culling {
+    pub enabled: bool,
+    position: Vec3,
+    forward: Vec3,
+    /// Half of the opening angle of the cone containing the frustum.
+    half_angle: f32,
+    pub max_distance: f32,
+}
+
+impl Culling {
+    pub fn new(position: Vec3, forward: Vec3, half_angle: f32, max_distance: f32) -> Self {
+        Self {
+            enabled: true,
+            position,
+            forward: forward.normalize(),
+            half_angle,
+            max_distance,
+        }
+    }
+
+    /// Culling for what `camera` sees, nothing further than `max_distance` is drawn.
+    pub fn from_camera(camera: &Camera3D, max_distance: f32) -> Self {
+        Self::new(
+            camera.position,
+            camera.target - camera.position,
+            corner_angle(camera.fovy, camera_aspect(camera)),
+            max_distance,
+        )
+    }
+
+    /// Copies of the world needed in every direction, see `repeat_for`.
+    pub fn repeat(&self) -> i32 {
+        repeat_for(self.max_distance)
+    }
+
+    /// Is any part of the sphere possibly visible?
+    pub fn is_visible(&self, center: Vec3, radius: f32) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        let to_center = center - self.position;
+        let distance = to_center.length();
+        if distance <= radius {
+            return true; // camera is inside
+        }
+        if distance - radius > self.max_distance {
+            return false;
+        }
+        let angle = self.forward.angle_between(to_center);
+        angle <= self.half_angle + (radius / distance).asin()
+    }
+}
+
+fn camera_aspect(camera: &Camera3D) -> f32 {
+    camera
+        .aspect
+        .unwrap_or(screen_width() / screen_height().max(1.0))
+}
+
+/// Angle between the view direction and the corners of the screen.
+fn corner_angle(fovy: f32, aspect: f32) -> f32 {
+    let half_height = (fovy * 0.5).tan();
+    (half_height * (1.0 + aspect * aspect).sqrt()).atan()
+}
+
+/// Furthest distance from the camera inside the view frustum. The far plane
+/// cuts the view at a depth, so the corners of the screen reach further than
+/// the middle, more so with a wider field of view.
+pub fn far_distance(fovy: f32, aspect: f32, z_far: f32) -> f32 {
+    z_far / corner_angle(fovy, aspect).cos()
+}
+
+/// How many copies of the world are drawn in every direction to show everything
+/// up to `distance`. The camera is somewhere in the base cube, so one more copy
+/// than fits into the distance.
+pub fn repeat_for(distance: f32) -> i32 {
+    (distance / SPACE_SIZE).floor() as i32 + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::math::vec3;
+
+    #[test]
+    fn test_distance_culling() {
+        let culling = Culling::new(Vec3::ZERO, Vec3::X, 0.5, 100.0);
+        assert!(culling.is_visible(vec3(50., 0., 0.), 1.0));
+        assert!(!culling.is_visible(vec3(120., 0., 0.), 1.0));
+        // A big sphere reaching into the view distance
+        assert!(culling.is_visible(vec3(120., 0., 0.), 30.0));
+    }
+
+    #[test]
+    fn test_frustum_culling() {
+        let culling = Culling::new(Vec3::ZERO, Vec3::X, 0.5, 100.0);
+        // behind the camera
+        assert!(!culling.is_visible(vec3(-20., 0., 0.), 1.0));
+        // to the side, outside and inside the cone
+        assert!(!culling.is_visible(vec3(10., 10., 0.), 1.0));
+        assert!(culling.is_visible(vec3(10., 5., 0.), 1.0));
+        // outside the cone, but the sphere touches it
+        assert!(culling.is_visible(vec3(10., 10., 0.), 5.0));
+        // camera inside the sphere
+        assert!(culling.is_visible(vec3(-1., 0., 0.), 2.0));
+    }
+
+    #[test]
+    fn test_repeat_from_fov() {
+        let fovy = 45.0_f32.to_radians();
+        // Straight ahead the far plane is at its distance
+        assert!((far_distance(0.0, 1.0, 100.0) - 100.0).abs() < 1e-3);
+        let narrow = far_distance(fovy, 4.0 / 3.0, SPACE_SIZE * 2.0);
+        let wide = far_distance(fovy * 2.0, 4.0 / 3.0, SPACE_SIZE * 2.0);
+        assert!(narrow > SPACE_SIZE * 2.0 && narrow < SPACE_SIZE * 3.0);
+        assert!(wide > SPACE_SIZE * 3.0);
+        assert_eq!(repeat_for(narrow), 3);
+        assert_eq!(repeat_for(wide), 4);
+        // The base cube and its neighbours are always drawn
+        assert_eq!(repeat_for(0.0), 1);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut culling = Culling::new(Vec3::ZERO, Vec3::X, 0.5, 100.0);
+        culling.enabled = false;
+        assert!(culling.is_visible(vec3(-1000., 0., 0.), 1.0));
+    }
+}
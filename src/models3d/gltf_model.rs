@@ -0,0 +1,309 @@
+use crate::assets::{sibling, AssetError};
+use base64::Engine;
+use gltf::{buffer, image, Gltf, Node};
+use macroquad::color::Color;
+use macroquad::math::{Mat3, Mat4, Vec2, Vec3};
+use macroquad::models::Vertex;
+use std::borrow::Cow;
+
+/// Triangles of one glTF primitive, already moved by its node transforms.
+pub struct Primitive {
+    /// Index of the glTF material, primitives with the same material share a texture.
+    pub material: Option<usize>,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+/// A parsed glTF or GLB file with all its buffers loaded.
+pub struct GltfFile<'a> {
+    path: &'a str,
+    gltf: Gltf,
+    buffers: Vec<Cow<'static, [u8]>>,
+}
+
+impl<'a> GltfFile<'a> {
+    /// Parses `data` and loads buffers embedded in the file, stored in the binary chunk
+    /// or in files next to it, which are read with `read_file`.
+    pub fn parse<F>(path: &'a str, data: &[u8], read_file: &F) -> Result<Self, AssetError>
+    where
+        F: Fn(&str) -> Result<Cow<'static, [u8]>, AssetError>,
+    {
+        let mut gltf = Gltf::from_slice(data).map_err(|error| AssetError::Gltf {
+            path: path.to_string(),
+            message: error.to_string(),
+        })?;
+        let mut blob = gltf.blob.take();
+        let mut buffers = Vec::new();
+        for buffer in gltf.buffers() {
+            let data = match buffer.source() {
+                buffer::Source::Bin => blob.take().map(Cow::Owned).ok_or(AssetError::Gltf {
+                    path: path.to_string(),
+                    message: "binary chunk missing".to_string(),
+                })?,
+                buffer::Source::Uri(uri) => read_uri(path, uri, read_file)?,
+            };
+            if data.len() < buffer.length() {
+                return Err(AssetError::Gltf {
+                    path: path.to_string(),
+                    message: format!("buffer {} is too short", buffer.index()),
+                });
+            }
+            buffers.push(data);
+        }
+        Ok(Self {
+            path,
+            gltf,
+            buffers,
+        })
+    }
+
+    /// Triangles of every mesh in the default scene (or the first scene), with node transforms
+    /// applied and the base colour of the material multiplied into the vertex colours.
+    pub fn primitives(&self) -> Result<Vec<Primitive>, AssetError> {
+        let scene = self
+            .gltf
+            .default_scene()
+            .or_else(|| self.gltf.scenes().next())
+            .ok_or(AssetError::InvalidMesh {
+                model: self.path.to_string(),
+                reason: "no scene found",
+            })?;
+        let mut primitives = Vec::new();
+        for node in scene.nodes() {
+            self.add_node(&node, Mat4::IDENTITY, &mut primitives)?;
+        }
+        Ok(primitives)
+    }
+
+    fn add_node(
+        &self,
+        node: &Node,
+        parent: Mat4,
+        primitives: &mut Vec<Primitive>,
+    ) -> Result<(), AssetError> {
+        let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+        if let Some(mesh) = node.mesh() {
+            // Normals have to stay perpendicular to surfaces under non uniform scaling
+            let normal_matrix = Mat3::from_mat4(transform).inverse().transpose();
+            for primitive in mesh.primitives() {
+                if primitive.mode() != gltf::mesh::Mode::Triangles {
+                    continue; // Lines and points are not used by the game
+                }
+                let invalid = |reason| AssetError::InvalidMesh {
+                    model: format!("{}/{}", self.path, mesh.name().unwrap_or("mesh")),
+                    reason,
+                };
+                let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+                let positions: Vec<[f32; 3]> = reader
+                    .read_positions()
+                    .ok_or_else(|| invalid("positions missing"))?
+                    .collect();
+                let normals: Vec<[f32; 3]> = reader
+                    .read_normals()
+                    .map(|normals| normals.collect())
+                    .unwrap_or_default();
+                let material = primitive.material();
+                let pbr = material.pbr_metallic_roughness();
+                let tex_set = pbr.base_color_texture().map_or(0, |info| info.tex_coord());
+                let tex_coords: Vec<[f32; 2]> = reader
+                    .read_tex_coords(tex_set)
+                    .map(|uvs| uvs.into_f32().collect())
+                    .unwrap_or_default();
+                let colors: Vec<[f32; 4]> = reader
+                    .read_colors(0)
+                    .map(|colors| colors.into_rgba_f32().collect())
+                    .unwrap_or_default();
+                let factor = pbr.base_color_factor();
+
+                let vertices = positions
+                    .iter()
+                    .enumerate()
+                    .map(|(i, position)| {
+                        let position = transform.transform_point3(Vec3::from(*position));
+                        let uv = tex_coords.get(i).map_or(Vec2::ZERO, |uv| Vec2::from(*uv));
+                        let color = colors.get(i).unwrap_or(&[1.0; 4]);
+                        let color = Color::new(
+                            color[0] * factor[0],
+                            color[1] * factor[1],
+                            color[2] * factor[2],
+                            color[3] * factor[3],
+                        );
+                        // glTF texture coordinates start at the top, like macroquad
+                        let mut vertex = Vertex::new2(position, uv, color);
+                        if let Some(normal) = normals.get(i) {
+                            vertex.normal = (normal_matrix * Vec3::from(*normal))
+                                .normalize_or_zero()
+                                .extend(0.0);
+                        }
+                        vertex
+                    })
+                    .collect();
+                let indices = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+                    None => (0..positions.len() as u32).collect(),
+                };
+                if indices.iter().any(|i| *i as usize >= positions.len()) {
+                    return Err(invalid("index out of range"));
+                }
+                primitives.push(Primitive {
+                    material: material.index(),
+                    vertices,
+                    indices,
+                });
+            }
+        }
+        for child in node.children() {
+            self.add_node(&child, transform, primitives)?;
+        }
+        Ok(())
+    }
+
+    /// Encoded base colour image of a material (PNG or JPEG), `None` for untextured materials.
+    pub fn base_color_image<F>(
+        &self,
+        material: Option<usize>,
+        read_file: &F,
+    ) -> Result<Option<Cow<'static, [u8]>>, AssetError>
+    where
+        F: Fn(&str) -> Result<Cow<'static, [u8]>, AssetError>,
+    {
+        let Some(material) = material.and_then(|i| self.gltf.materials().nth(i)) else {
+            return Ok(None);
+        };
+        let Some(info) = material.pbr_metallic_roughness().base_color_texture() else {
+            return Ok(None);
+        };
+        match info.texture().source().source() {
+            image::Source::View { view, .. } => {
+                let buffer = &self.buffers[view.buffer().index()];
+                let data = buffer
+                    .get(view.offset()..view.offset() + view.length())
+                    .ok_or(AssetError::Gltf {
+                        path: self.path.to_string(),
+                        message: format!("image view {} out of range", view.index()),
+                    })?;
+                Ok(Some(Cow::Owned(data.to_vec())))
+            }
+            image::Source::Uri { uri, .. } => read_uri(self.path, uri, read_file).map(Some),
+        }
+    }
+}
+
+/// Reads data URIs directly and everything else relative to the model file.
+fn read_uri<F>(path: &str, uri: &str, read_file: &F) -> Result<Cow<'static, [u8]>, AssetError>
+where
+    F: Fn(&str) -> Result<Cow<'static, [u8]>, AssetError>,
+{
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data.split_once(";base64,").ok_or(AssetError::Gltf {
+            path: path.to_string(),
+            message: "only base64 data URIs are supported".to_string(),
+        })?;
+        return base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map(Cow::Owned)
+            .map_err(|error| AssetError::Gltf {
+                path: path.to_string(),
+                message: error.to_string(),
+            });
+    }
+    read_file(&sibling(path, &percent_decode(uri)))
+}
+
+/// URIs are percent-encoded (RFC 3986), `%XX` is a byte of the UTF-8 file name.
+/// A `%` that doesn't start an escape is kept.
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        // from_str_radix also takes a sign, like "+5"
+        let escape = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escape) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_files(path: &str) -> Result<Cow<'static, [u8]>, AssetError> {
+        Err(AssetError::File {
+            path: path.to_string(),
+            message: "not found".to_string(),
+        })
+    }
+
+    /// One triangle in a node moved by (10, 0, 0), with a red material.
+    fn triangle_gltf() -> String {
+        let mut buffer = Vec::new();
+        for value in [0.0f32, 0., 0., 1., 0., 0., 0., 1., 0.] {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&buffer);
+        format!(
+            r#"{{
+            "asset": {{"version": "2.0"}},
+            "scene": 0,
+            "scenes": [{{"nodes": [0]}}],
+            "nodes": [{{"mesh": 0, "translation": [10, 0, 0]}}],
+            "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "material": 0}}]}}],
+            "materials": [{{"pbrMetallicRoughness": {{"baseColorFactor": [1, 0, 0, 1]}}}}],
+            "buffers": [{{"byteLength": 36, "uri": "data:application/octet-stream;base64,{}"}}],
+            "bufferViews": [{{"buffer": 0, "byteLength": 36}}],
+            "accessors": [{{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                "min": [0, 0, 0], "max": [1, 1, 0]}}]
+        }}"#,
+            encoded
+        )
+    }
+
+    #[test]
+    fn test_triangle() {
+        let json = triangle_gltf();
+        let file = GltfFile::parse("model.gltf", json.as_bytes(), &no_files).unwrap();
+        let primitives = file.primitives().unwrap();
+        assert_eq!(primitives.len(), 1);
+        let primitive = &primitives[0];
+        assert_eq!(primitive.material, Some(0));
+        assert_eq!(primitive.indices, vec![0, 1, 2]);
+        assert_eq!(primitive.vertices[1].position, Vec3::new(11., 0., 0.));
+        assert_eq!(primitive.vertices[0].color, [255, 0, 0, 255]);
+        assert!(file.base_color_image(Some(0), &no_files).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("my%20model.bin"), "my model.bin");
+        assert_eq!(percent_decode("apfel%20%281%29.png"), "apfel (1).png");
+        assert_eq!(percent_decode("caf%C3%A9.png"), "café.png");
+        assert_eq!(percent_decode("100%25.png"), "100%.png");
+        // Not an escape
+        assert_eq!(percent_decode("50%.png"), "50%.png");
+        assert_eq!(percent_decode("%zz%2"), "%zz%2");
+        assert_eq!(percent_decode("%+5%-1.png"), "%+5%-1.png");
+    }
+
+    #[test]
+    fn test_missing_buffer_file() {
+        let json = triangle_gltf().replace("data:application/octet-stream;base64,", "missing.bin?");
+        match GltfFile::parse("models/model.gltf", json.as_bytes(), &no_files) {
+            Err(AssetError::File { path, .. }) => assert!(path.starts_with("models/missing.bin")),
+            _ => panic!("expected a missing file error"),
+        }
+    }
+}
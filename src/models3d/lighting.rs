@@ -0,0 +1,204 @@
+use crate::assets::{AssetError, LoadReport};
+use crate::draw_utils::SPACE_SIZE;
+use macroquad::color::Color;
+use macroquad::material::{gl_use_default_material, gl_use_material, load_material};
+use macroquad::material::{Material, MaterialParams};
+use macroquad::math::{vec3, Vec3};
+use macroquad::miniquad::{Comparison, PipelineParams, ShaderSource, UniformDesc, UniformType};
+
+const VERTEX: &str = r#"#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+attribute vec4 normal;
+
+varying lowp vec2 uv;
+varying lowp vec4 color;
+varying mediump vec3 world_normal;
+varying mediump vec3 world_position;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+void main() {
+    vec4 world = Model * vec4(position, 1);
+    gl_Position = Projection * world;
+    world_position = world.xyz;
+    world_normal = (Model * vec4(normal.xyz, 0.0)).xyz;
+    color = color0 / 255.0;
+    uv = texcoord;
+}"#;
+
+const FRAGMENT: &str = r#"#version 100
+precision mediump float;
+
+varying lowp vec2 uv;
+varying lowp vec4 color;
+varying mediump vec3 world_normal;
+varying mediump vec3 world_position;
+
+uniform sampler2D Texture;
+uniform vec3 LightDirection;
+uniform vec3 CameraPosition;
+uniform float Ambient;
+uniform float RimStrength;
+uniform vec3 FogColor;
+uniform float FogStart;
+uniform float FogEnd;
+
+void main() {
+    vec4 base = color * texture2D(Texture, uv);
+    vec3 lit = base.rgb;
+    // Models without normals are drawn unlit
+    if (length(world_normal) > 0.001) {
+        vec3 n = normalize(world_normal);
+        float diffuse = max(dot(n, LightDirection), 0.0);
+        vec3 to_camera = normalize(CameraPosition - world_position);
+        float rim = RimStrength * pow(1.0 - max(dot(n, to_camera), 0.0), 2.0);
+        float light = Ambient + (1.0 - Ambient) * diffuse;
+        lit = base.rgb * light + vec3(rim);
+    }
+    float distance = length(CameraPosition - world_position);
+    float fog = clamp((distance - FogStart) / (FogEnd - FogStart), 0.0, 1.0);
+    gl_FragColor = vec4(mix(lit, FogColor, fog), base.a);
+}"#;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum LightingMode {
+    /// Textures only, like the default macroquad pipeline.
+    Flat,
+    Directional,
+    /// Directional light with a bright outline.
+    DirectionalRim,
+}
+
+/// Linear distance fog, measured from the camera.
+#[derive(Debug, Copy, Clone)]
+pub struct Fog {
+    pub enabled: bool,
+    pub color: Color,
+    /// Distance where the fog starts.
+    pub start: f32,
+    /// Distance where everything has the fog color.
+    pub end: f32,
+}
+
+impl Fog {
+    /// Distance where everything is hidden, `None` when the fog is off.
+    pub fn distance(&self) -> Option<f32> {
+        self.enabled.then_some(self.end)
+    }
+}
+
+/** Material with a directional light and an ambient term, using vertex normals,
+and distance fog, so the repeated copies fade into the background.
+Everything drawn between `apply` and `reset` uses it. If the shader can't be
+compiled, macroquad's default material is used, without light and fog.
+*/
+pub struct Lighting {
+    /// `None` when the GPU rejected the shader.
+    material: Option<Material>,
+    pub mode: LightingMode,
+    /// Direction the light is coming from (pointing towards the light).
+    pub light_direction: Vec3,
+    pub ambient: f32,
+    pub rim_strength: f32,
+    pub fog: Fog,
+}
+
+impl Lighting {
+    pub fn new(fog_color: Color, report: &mut LoadReport) -> Self {
+        let material = report.check(load_lighting_material());
+        Self {
+            // Only the shader draws the fog, without it the whole view is filled
+            fog: Fog {
+                enabled: material.is_some(),
+                color: fog_color,
+                start: SPACE_SIZE * 0.75,
+                end: SPACE_SIZE * 2.5,
+            },
+            material,
+            mode: LightingMode::DirectionalRim,
+            light_direction: vec3(0.4, 0.8, 0.3).normalize(),
+            ambient: 0.45,
+            rim_strength: 0.25,
+        }
+    }
+
+    /// Fog stays off without the shader.
+    pub fn toggle_fog(&mut self) {
+        self.fog.enabled = !self.fog.enabled && self.material.is_some();
+    }
+
+    pub fn next_mode(&mut self) {
+        self.mode = match self.mode {
+            LightingMode::Flat => LightingMode::Directional,
+            LightingMode::Directional => LightingMode::DirectionalRim,
+            LightingMode::DirectionalRim => LightingMode::Flat,
+        };
+    }
+
+    /// Starts drawing with lighting and fog, `camera_position` is used for rim lighting and fog.
+    pub fn apply(&self, camera_position: Vec3) {
+        let (ambient, rim_strength) = match self.mode {
+            LightingMode::Flat => (1.0, 0.0),
+            LightingMode::Directional => (self.ambient, 0.0),
+            LightingMode::DirectionalRim => (self.ambient, self.rim_strength),
+        };
+        // Disabled fog starts further than anything is drawn
+        let (fog_start, fog_end) = if self.fog.enabled {
+            (self.fog.start, self.fog.end)
+        } else {
+            (5_000.0, 10_000.0) // mediump floats are not much bigger than this
+        };
+        let fog_color = vec3(self.fog.color.r, self.fog.color.g, self.fog.color.b);
+        let Some(material) = &self.material else {
+            gl_use_default_material();
+            return;
+        };
+        material.set_uniform("LightDirection", self.light_direction.normalize());
+        material.set_uniform("CameraPosition", camera_position);
+        material.set_uniform("Ambient", ambient);
+        material.set_uniform("RimStrength", rim_strength);
+        material.set_uniform("FogColor", fog_color);
+        material.set_uniform("FogStart", fog_start);
+        material.set_uniform("FogEnd", fog_end);
+        gl_use_material(material);
+    }
+
+    pub fn reset(&self) {
+        gl_use_default_material();
+    }
+}
+
+/// Compiles the lighting shader, some drivers reject GLSL that others accept.
+fn load_lighting_material() -> Result<Material, AssetError> {
+    load_material(
+        ShaderSource::Glsl {
+            vertex: VERTEX,
+            fragment: FRAGMENT,
+        },
+        MaterialParams {
+            // Same depth settings as macroquad uses for 3D meshes
+            pipeline_params: PipelineParams {
+                depth_write: true,
+                depth_test: Comparison::LessOrEqual,
+                ..Default::default()
+            },
+            uniforms: vec![
+                UniformDesc::new("LightDirection", UniformType::Float3),
+                UniformDesc::new("CameraPosition", UniformType::Float3),
+                UniformDesc::new("Ambient", UniformType::Float1),
+                UniformDesc::new("RimStrength", UniformType::Float1),
+                UniformDesc::new("FogColor", UniformType::Float3),
+                UniformDesc::new("FogStart", UniformType::Float1),
+                UniformDesc::new("FogEnd", UniformType::Float1),
+            ],
+            ..Default::default()
+        },
+    )
+    .map_err(|error| AssetError::Shader {
+        name: "lighting",
+        message: error.to_string(),
+    })
+}
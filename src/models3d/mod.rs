@@ -1,5 +1,10 @@
+mod culling;
+mod gltf_model;
+mod lighting;
 pub mod model3d;
 mod multi_model;
 
-pub use model3d::Model3D;
-pub use multi_model::MultiModel;
+pub use culling::{far_distance, repeat_for, Culling};
+pub use lighting::Lighting;
+pub use model3d::{Model3D, ModelOptions};
+pub use multi_model::{DrawStats, MultiModel};
@@ -1,41 +1,281 @@
-use image::ImageReader;
+use crate::assets::{checkerboard_texture, sibling, AssetError, LoadReport};
+use crate::models3d::gltf_model::GltfFile;
 use macroquad::color::WHITE;
-use macroquad::math::{vec4, Mat4};
+use macroquad::conf::Conf;
+use macroquad::math::{vec4, Mat4, Quat, Vec3};
 use macroquad::models::{draw_mesh, Mesh, Vertex};
 use macroquad::prelude::get_internal_gl;
 use macroquad::texture::{FilterMode, Texture2D};
-use std::path::Path;
-use tobj::{load_obj, Material};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::OnceLock;
+use tobj::{load_mtl_buf, load_obj_buf, LoadError, Material};
+
+/// Largest meshes that macroquad draws in one piece. Models are split into
+/// chunks of this size when they are loaded.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChunkLimits {
+    pub vertices: usize,
+    pub indices: usize,
+}
+
+impl ChunkLimits {
+    /// Limits for the draw call buffers macroquad was started with.
+    pub fn from_conf(conf: &Conf) -> Self {
+        // geometry() drops anything that reaches the capacity, so chunks stay one
+        // below it. Indices are u16, so a chunk can't have more vertices than that.
+        Self {
+            vertices: conf
+                .draw_call_vertex_capacity
+                .saturating_sub(1)
+                .min(u16::MAX as usize),
+            indices: conf.draw_call_index_capacity.saturating_sub(1),
+        }
+    }
+}
+
+static CHUNK_LIMITS: OnceLock<ChunkLimits> = OnceLock::new();
+
+/// Called with the `Conf` from `window_conf` before any model is loaded.
+/// Models split before would already use the default limits.
+pub fn set_chunk_limits(limits: ChunkLimits) {
+    let result = CHUNK_LIMITS.set(limits);
+    debug_assert!(
+        result.is_ok(),
+        "chunk limits are set twice or after a model was split"
+    );
+}
+
+/// macroquad's default buffers if nothing was set, like in tests.
+fn chunk_limits() -> ChunkLimits {
+    *CHUNK_LIMITS.get_or_init(|| ChunkLimits::from_conf(&Conf::default()))
+}
+
+/** How a model file is placed in the game. Models are made at different sizes
+and facing different directions, this brings them to the game's units,
+facing forward (+X) with the top up (+Y).
+*/
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ModelOptions {
+    /// Size of one model unit in game units.
+    pub scale: f32,
+    /// Applied after scaling.
+    pub rotation: Quat,
+    /// Texture filtering. The default is Minecraft style pixelated textures,
+    /// the Linear option blurs the texture.
+    pub filter: FilterMode,
+}
+
+impl Default for ModelOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            rotation: Quat::IDENTITY,
+            filter: FilterMode::Nearest,
+        }
+    }
+}
+
+impl ModelOptions {
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(Vec3::splat(self.scale), self.rotation, Vec3::ZERO)
+    }
+}
 
 pub struct Model3D {
     pub meshes: Vec<Mesh>,
 }
 
 impl Model3D {
-    pub fn from_file(path: &str) -> Model3D {
+    /// Loads a model from a buffer, the format is chosen by the extension of `path`
+    /// (`.gltf` and `.glb` for glTF, OBJ otherwise). `path` is also used for finding
+    /// material files, textures and buffers next to it, which are read with `read_file`.
+    /// Missing materials and textures are replaced by a checkerboard and added
+    /// to `report`, a model that can't be used at all is an error.
+    pub fn from_memory<F>(
+        path: &str,
+        data: &[u8],
+        read_file: F,
+        options: &ModelOptions,
+        report: &mut LoadReport,
+    ) -> Result<Model3D, AssetError>
+    where
+        F: Fn(&str) -> Result<Cow<'static, [u8]>, AssetError>,
+    {
+        let extension = path.rsplit('.').next().unwrap_or("").to_lowercase();
+        let mut model = match extension.as_str() {
+            "gltf" | "glb" => Self::from_gltf(path, data, &read_file, report)?,
+            _ => Self::from_obj(path, data, &read_file, report)?,
+        };
+        if model.meshes.is_empty() {
+            return Err(AssetError::InvalidMesh {
+                model: path.to_string(),
+                reason: "no meshes found",
+            });
+        }
+        model.apply_options(options);
+        Ok(model)
+    }
+
+    fn from_obj<F>(
+        path: &str,
+        obj: &[u8],
+        read_file: &F,
+        report: &mut LoadReport,
+    ) -> Result<Model3D, AssetError>
+    where
+        F: Fn(&str) -> Result<Cow<'static, [u8]>, AssetError>,
+    {
+        // tobj only gets to know that the material file failed, keep the reason
+        let material_error = RefCell::new(None);
         let (mut models, materials) =
-            load_obj(path, &tobj::GPU_LOAD_OPTIONS).expect("failed to load model file");
-        let materials = materials.expect("failed to load materials file");
-        let material_path = Path::new(path).parent().unwrap();
-
-        let mut meshes = Vec::new();
-        // Merge meshes with the same texture into one, so fewer calls to GPU are required.
-        // Could backfire with large models, but we will not be using those anyway.
-        models.sort_by_key(|m| m.mesh.material_id.expect("no material id"));
+            load_obj_buf(&mut Cursor::new(obj), &tobj::GPU_LOAD_OPTIONS, |mtl_path| {
+                let name = mtl_path.to_string_lossy();
+                let data = read_file(&sibling(path, &name)).map_err(|error| {
+                    material_error.replace(Some(error));
+                    LoadError::OpenFileFailed
+                })?;
+                load_mtl_buf(&mut Cursor::new(data))
+            })
+            .map_err(|error| AssetError::Model {
+                path: path.to_string(),
+                error,
+            })?;
+        let materials_failed = materials.is_err();
+        let materials = report
+            .check(materials.map_err(|error| {
+                material_error
+                    .take()
+                    .unwrap_or_else(|| AssetError::Materials {
+                        path: path.to_string(),
+                        error,
+                    })
+            }))
+            .unwrap_or_default();
+
+        let mut meshes: Vec<Mesh> = Vec::new();
+        // Every material is loaded once, also when its texture failed
+        let mut textures: HashMap<Option<usize>, Texture2D> = HashMap::new();
+        models.sort_by_key(|m| m.mesh.material_id);
+        let mut last_mat_id = None;
         for model in models.iter() {
-            let mat_id = model.mesh.material_id.unwrap();
-            let material = &materials[mat_id];
-            let mut mesh = obj_to_mesh(model, load_diffuse(material_path, material));
-            if meshes.len() <= mat_id {
-                meshes.push(mesh);
-            } else {
-                let start_index = meshes[mat_id].vertices.len() as u16;
-                mesh.indices.iter_mut().for_each(|idx| *idx += start_index);
-                meshes[mat_id].vertices.extend(mesh.vertices.iter());
-                meshes[mat_id].indices.extend(mesh.indices.iter());
+            let mat_id = model.mesh.material_id;
+            let texture = match textures.get(&mat_id) {
+                Some(texture) => texture.clone(),
+                None => {
+                    let texture = match mat_id.and_then(|id| materials.get(id)) {
+                        Some(material) => report.check(load_diffuse(path, material, read_file)),
+                        // Already reported when the materials file failed to load
+                        None if materials_failed => None,
+                        None => {
+                            report.push(AssetError::NoTexture {
+                                path: path.to_string(),
+                                material: model.name.clone(),
+                            });
+                            None
+                        }
+                    }
+                    .unwrap_or_else(checkerboard_texture);
+                    textures.insert(mat_id, texture.clone());
+                    texture
+                }
+            };
+            for mesh in obj_to_mesh(model, texture)? {
+                push_merged(&mut meshes, mesh, last_mat_id == Some(mat_id));
+                last_mat_id = Some(mat_id);
             }
         }
-        Model3D { meshes }
+        Ok(Model3D { meshes })
+    }
+
+    fn from_gltf<F>(
+        path: &str,
+        data: &[u8],
+        read_file: &F,
+        report: &mut LoadReport,
+    ) -> Result<Model3D, AssetError>
+    where
+        F: Fn(&str) -> Result<Cow<'static, [u8]>, AssetError>,
+    {
+        let file = GltfFile::parse(path, data, read_file)?;
+        let mut primitives = file.primitives()?;
+        primitives.sort_by_key(|p| p.material);
+
+        let mut meshes: Vec<Mesh> = Vec::new();
+        let mut textures: HashMap<Option<usize>, Texture2D> = HashMap::new();
+        let mut last_material = None;
+        for primitive in primitives {
+            let material = primitive.material;
+            let texture = match textures.get(&material) {
+                Some(texture) => texture.clone(),
+                None => {
+                    let texture = match file.base_color_image(material, read_file) {
+                        // Colours come from the vertices
+                        Ok(None) => white_texture(),
+                        Ok(Some(image)) => report
+                            .check(texture_from_image(path, &image))
+                            .unwrap_or_else(checkerboard_texture),
+                        Err(error) => {
+                            report.push(error);
+                            checkerboard_texture()
+                        }
+                    };
+                    textures.insert(material, texture.clone());
+                    texture
+                }
+            };
+            let chunks = split_mesh(
+                &primitive.vertices,
+                &primitive.indices,
+                chunk_limits().vertices,
+                chunk_limits().indices,
+            );
+            for (vertices, indices) in chunks {
+                let mesh = Mesh {
+                    vertices,
+                    indices,
+                    texture: Some(texture.clone()),
+                };
+                push_merged(&mut meshes, mesh, last_material == Some(material));
+                last_material = Some(material);
+            }
+        }
+        Ok(Model3D { meshes })
+    }
+
+    /// Moves every vertex from model units to game units.
+    fn apply_options(&mut self, options: &ModelOptions) {
+        for mesh in &self.meshes {
+            if let Some(texture) = &mesh.texture {
+                texture.set_filter(options.filter);
+            }
+        }
+        if options.scale == 1.0 && options.rotation == Quat::IDENTITY {
+            return;
+        }
+        let matrix = options.matrix();
+        for vertex in self
+            .meshes
+            .iter_mut()
+            .flat_map(|mesh| mesh.vertices.iter_mut())
+        {
+            vertex.position = matrix.transform_point3(vertex.position);
+            vertex.normal = (options.rotation * vertex.normal.truncate()).extend(0.0);
+        }
+    }
+
+    /// Checkerboard cube, about the size of a snake segment.
+    pub fn placeholder() -> Model3D {
+        let (vertices, indices) = cube(4.0);
+        Model3D {
+            meshes: vec![Mesh {
+                vertices,
+                indices,
+                texture: Some(checkerboard_texture()),
+            }],
+        }
     }
 
     pub fn _draw_meshes(&self, model_matrix: Mat4) {
@@ -50,33 +290,83 @@ impl Model3D {
     }
 }
 
-pub fn load_diffuse(material_path: &Path, material: &Material) -> Texture2D {
+/// Loads the texture of `material`, the file name is relative to the model at `path`.
+pub fn load_diffuse<F>(
+    path: &str,
+    material: &Material,
+    read_file: &F,
+) -> Result<Texture2D, AssetError>
+where
+    F: Fn(&str) -> Result<Cow<'static, [u8]>, AssetError>,
+{
     let texture = material
         .diffuse_texture
         .clone()
-        .expect("no diffuse texture specified");
-    let file_name = Path::new(material_path).join(Path::new(&texture));
+        .ok_or_else(|| AssetError::NoTexture {
+            path: path.to_string(),
+            material: material.name.clone(),
+        })?;
+    let file_name = sibling(path, &texture);
     // There are functions for loading textures directly from file, but we had some problems with that.
-    let image = ImageReader::open(file_name)
-        .expect("failed to load image file")
-        .decode()
-        .expect("failed to decode image");
+    let data = read_file(&file_name)?;
+    texture_from_image(&file_name, &data)
+}
 
-    Texture2D::from_rgba8(
+/// Decodes a PNG or JPEG image into a texture.
+pub fn texture_from_image(path: &str, data: &[u8]) -> Result<Texture2D, AssetError> {
+    let image = image::load_from_memory(data).map_err(|error| AssetError::Image {
+        path: path.to_string(),
+        error,
+    })?;
+    Ok(Texture2D::from_rgba8(
         image.width() as u16,
         image.height() as u16,
         &image.to_rgba8(),
-    )
+    ))
+}
+
+fn white_texture() -> Texture2D {
+    Texture2D::from_rgba8(1, 1, &[255; 4])
 }
 
-pub fn obj_to_mesh(model: &tobj::Model, texture: Texture2D) -> Mesh {
+/// Merges meshes with the same texture into one, so fewer calls to GPU are required.
+/// Merged meshes still have to fit into one draw call, otherwise a new mesh is started.
+fn push_merged(meshes: &mut Vec<Mesh>, mut mesh: Mesh, same_texture: bool) {
+    match meshes.last_mut() {
+        Some(last)
+            if same_texture
+                && last.vertices.len() + mesh.vertices.len() <= chunk_limits().vertices
+                && last.indices.len() + mesh.indices.len() <= chunk_limits().indices =>
+        {
+            let start_index = last.vertices.len() as u16;
+            mesh.indices.iter_mut().for_each(|idx| *idx += start_index);
+            last.vertices.extend(mesh.vertices.iter());
+            last.indices.extend(mesh.indices.iter());
+        }
+        _ => meshes.push(mesh),
+    }
+}
+
+/// Converts a model to meshes, large models are split into several meshes
+/// that fit into one draw call.
+pub fn obj_to_mesh(model: &tobj::Model, texture: Texture2D) -> Result<Vec<Mesh>, AssetError> {
+    let invalid = |reason| AssetError::InvalidMesh {
+        model: model.name.clone(),
+        reason,
+    };
     let mut vertices: Vec<Vertex> = Vec::new();
     let max_index = *model
         .mesh
         .indices
         .iter()
         .max()
-        .expect("No mesh indices found");
+        .ok_or_else(|| invalid("no mesh indices found"))?;
+    if model.mesh.positions.len() <= max_index as usize * 3 + 2 {
+        return Err(invalid("index out of range"));
+    }
+    if model.mesh.texcoords.len() <= max_index as usize * 2 + 1 {
+        return Err(invalid("texture coordinates missing"));
+    }
 
     for i in 0..=max_index {
         let i = i as usize;
@@ -84,8 +374,8 @@ pub fn obj_to_mesh(model: &tobj::Model, texture: Texture2D) -> Mesh {
         let y = model.mesh.positions[i * 3 + 1];
         let z = model.mesh.positions[i * 3 + 2];
 
-        let u = model.mesh.texcoords.get(i * 2).unwrap();
-        let v = model.mesh.texcoords.get(i * 2 + 1).unwrap();
+        let u = model.mesh.texcoords[i * 2];
+        let v = model.mesh.texcoords[i * 2 + 1];
 
         // Is allowed to not exist (normals are not used by default macroquad)
         let nx = model.mesh.normals.get(i * 3);
@@ -96,26 +386,204 @@ pub fn obj_to_mesh(model: &tobj::Model, texture: Texture2D) -> Mesh {
             _ => vec4(0., 0., 0., 0.),
         };
 
-        let scale = 20.0;
         // I think (not sure, but it looked like that) that color from texture is multiplied by color,
         // so set it to white to get the same color as in texture.
         // Be careful: v coordinate is inverted.
-        let mut vertex = Vertex::new(x * scale, y * scale, z * scale, *u, 1. - v, WHITE);
+        let mut vertex = Vertex::new(x, y, z, u, 1. - v, WHITE);
         vertex.normal = normal;
         vertices.push(vertex);
     }
 
-    let indices = model
-        .mesh
-        .indices
-        .iter()
-        .map(|i| *i as u16)
-        .collect::<Vec<u16>>();
-    // Minecraft style pixelated textures. The Linear option blurs the texture.
-    texture.set_filter(FilterMode::Nearest);
-    Mesh {
+    Ok(split_mesh(
+        &vertices,
+        &model.mesh.indices,
+        chunk_limits().vertices,
+        chunk_limits().indices,
+    )
+    .into_iter()
+    .map(|(vertices, indices)| Mesh {
         vertices,
         indices,
-        texture: Some(texture),
+        texture: Some(texture.clone()),
+    })
+    .collect())
+}
+
+/// Cube centred on the origin with its own texture coordinates and normals on every face.
+pub fn cube(half_size: f32) -> (Vec<Vertex>, Vec<u16>) {
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+    for normal in [
+        Vec3::X,
+        Vec3::NEG_X,
+        Vec3::Y,
+        Vec3::NEG_Y,
+        Vec3::Z,
+        Vec3::NEG_Z,
+    ] {
+        // Two directions along the face, so that a x b = normal
+        let a = normal.any_orthonormal_vector();
+        let b = normal.cross(a);
+        let start = vertices.len() as u16;
+        for (u, v) in [(0., 0.), (1., 0.), (1., 1.), (0., 1.)] {
+            let p = (normal + a * (u * 2. - 1.) + b * (v * 2. - 1.)) * half_size;
+            let mut vertex = Vertex::new(p.x, p.y, p.z, u, v, WHITE);
+            vertex.normal = normal.extend(0.0);
+            vertices.push(vertex);
+        }
+        indices.extend([start, start + 1, start + 2, start, start + 2, start + 3]);
+    }
+    (vertices, indices)
+}
+
+/// Splits triangles into chunks with at most `max_vertices` vertices and `max_indices` indices,
+/// so they can be indexed with u16. Vertices shared by triangles in different chunks are copied.
+pub fn split_mesh(
+    vertices: &[Vertex],
+    indices: &[u32],
+    max_vertices: usize,
+    max_indices: usize,
+) -> Vec<(Vec<Vertex>, Vec<u16>)> {
+    assert!(max_vertices >= 3 && max_indices >= 3);
+    let max_vertices = max_vertices.min(u16::MAX as usize + 1);
+    if vertices.len() <= max_vertices && indices.len() <= max_indices {
+        // Common case, nothing to split
+        let indices = indices.iter().map(|i| *i as u16).collect();
+        return vec![(vertices.to_vec(), indices)];
+    }
+
+    let mut chunks = Vec::new();
+    let mut chunk_vertices: Vec<Vertex> = Vec::new();
+    let mut chunk_indices: Vec<u16> = Vec::new();
+    // Index in the original mesh -> index in the current chunk
+    let mut remap: HashMap<u32, u16> = HashMap::new();
+    for triangle in indices.chunks(3) {
+        let new_vertices = triangle.iter().filter(|i| !remap.contains_key(i)).count();
+        if chunk_vertices.len() + new_vertices > max_vertices
+            || chunk_indices.len() + triangle.len() > max_indices
+        {
+            chunks.push((
+                std::mem::take(&mut chunk_vertices),
+                std::mem::take(&mut chunk_indices),
+            ));
+            remap.clear();
+        }
+        for &index in triangle {
+            let new_index = *remap.entry(index).or_insert_with(|| {
+                chunk_vertices.push(vertices[index as usize]);
+                (chunk_vertices.len() - 1) as u16
+            });
+            chunk_indices.push(new_index);
+        }
+    }
+    if !chunk_indices.is_empty() {
+        chunks.push((chunk_vertices, chunk_indices));
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A strip of `n` triangles, each one sharing two vertices with the previous one.
+    fn strip(n: u32) -> (Vec<Vertex>, Vec<u32>) {
+        let vertices = (0..n + 2)
+            .map(|i| Vertex::new(i as f32, (i % 2) as f32, 0.0, 0.0, 0.0, WHITE))
+            .collect();
+        let indices = (0..n).flat_map(|i| [i, i + 1, i + 2]).collect();
+        (vertices, indices)
+    }
+
+    fn check_chunks(
+        vertices: &[Vertex],
+        indices: &[u32],
+        chunks: &[(Vec<Vertex>, Vec<u16>)],
+        max_vertices: usize,
+        max_indices: usize,
+    ) {
+        // Same triangles in the same order, and every chunk within limits
+        let mut triangles = indices.chunks(3);
+        for (chunk_vertices, chunk_indices) in chunks {
+            assert!(chunk_vertices.len() <= max_vertices);
+            assert!(chunk_indices.len() <= max_indices);
+            for triangle in chunk_indices.chunks(3) {
+                let original = triangles.next().unwrap();
+                for (a, b) in triangle.iter().zip(original) {
+                    assert_eq!(
+                        chunk_vertices[*a as usize].position,
+                        vertices[*b as usize].position
+                    );
+                }
+            }
+        }
+        assert!(triangles.next().is_none());
+    }
+
+    #[test]
+    fn test_small_mesh_is_not_split() {
+        let (vertices, indices) = strip(10);
+        let chunks = split_mesh(&vertices, &indices, 100, 100);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].0.len(), vertices.len());
+        check_chunks(&vertices, &indices, &chunks, 100, 100);
+    }
+
+    #[test]
+    fn test_split_by_vertices() {
+        let (vertices, indices) = strip(100);
+        let chunks = split_mesh(&vertices, &indices, 20, 1000);
+        assert!(chunks.len() > 1);
+        check_chunks(&vertices, &indices, &chunks, 20, 1000);
+    }
+
+    #[test]
+    fn test_split_by_indices() {
+        let (vertices, indices) = strip(100);
+        let chunks = split_mesh(&vertices, &indices, 1000, 30);
+        assert_eq!(chunks.len(), 10);
+        check_chunks(&vertices, &indices, &chunks, 1000, 30);
+    }
+
+    #[test]
+    fn test_cube_faces_point_outwards() {
+        let (vertices, indices) = cube(2.0);
+        assert_eq!(vertices.len(), 24);
+        assert_eq!(indices.len(), 36);
+        for triangle in indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| vertices[triangle[i] as usize].position);
+            let normal = vertices[triangle[0] as usize].normal.truncate();
+            // Counter clockwise seen from outside, and on the surface of the cube
+            assert!((b - a).cross(c - a).dot(normal) > 0.0);
+            assert!((a.dot(normal) - 2.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_chunk_limits() {
+        let limits = ChunkLimits::from_conf(&Conf::default());
+        assert_eq!((limits.vertices, limits.indices), (9999, 4999));
+        let huge = ChunkLimits::from_conf(&Conf {
+            draw_call_vertex_capacity: 1_000_000,
+            draw_call_index_capacity: 1_000_000,
+            ..Default::default()
+        });
+        assert_eq!(huge.vertices, u16::MAX as usize);
+        assert_eq!(huge.indices, 999_999);
+    }
+
+    #[test]
+    fn test_split_over_u16() {
+        // More vertices than u16 can index
+        let (vertices, indices) = strip(70_000);
+        let chunks = split_mesh(&vertices, &indices, usize::MAX, usize::MAX);
+        assert_eq!(chunks.len(), 2);
+        check_chunks(
+            &vertices,
+            &indices,
+            &chunks,
+            u16::MAX as usize + 1,
+            usize::MAX,
+        );
     }
 }
@@ -1,163 +1,317 @@
 use crate::draw_utils::SPACE_SIZE;
-use crate::models3d::Model3D;
-use macroquad::math::{vec3, Mat4, Vec4};
-use macroquad::models::{Mesh, Vertex};
+use crate::models3d::{Culling, Model3D};
+use macroquad::color::Color;
+use macroquad::math::{vec3, Mat3, Mat4, Vec3};
+use macroquad::models::Vertex;
 use macroquad::prelude::{get_internal_gl, DrawMode};
-use macroquad::texture::Texture2D;
 use std::collections::HashMap;
+use std::rc::Rc;
 
-struct PartialMesh {
-    vertices: Vec<Vertex>,
-    indices: Vec<u16>,
-    texture_id: usize,
+/// One placed copy of the base model.
+struct Instance {
+    id: usize,
+    /// Transform of this instance, without the base transform.
+    transform: Mat4,
+    /// Vertices of every base mesh with the transform already applied.
+    /// Indices are the same as in the base mesh, so they are not stored.
+    meshes: Vec<Vec<Vertex>>,
+    // Bounding sphere, used for culling
+    center: Vec3,
+    radius: f32,
+}
+
+impl Instance {
+    fn new(
+        id: usize,
+        base_model: &Model3D,
+        transform: Mat4,
+        base_transform: Mat4,
+        tint: [u8; 4],
+    ) -> Self {
+        let mut instance = Instance {
+            id,
+            transform,
+            meshes: base_model
+                .meshes
+                .iter()
+                .map(|mesh| mesh.vertices.clone())
+                .collect(),
+            center: Vec3::ZERO,
+            radius: 0.0,
+        };
+        instance.bake(base_model, base_transform, tint);
+        instance
+    }
+
+    /// Rewrites the vertices in place, nothing is allocated.
+    fn bake(&mut self, base_model: &Model3D, base_transform: Mat4, tint: [u8; 4]) {
+        let transform = self.transform.mul_mat4(&base_transform);
+        // Normals only need the rotation part of the transform, scaling is undone by normalizing.
+        let rotation = Mat3::from_mat4(transform);
+        let mut min = Vec3::splat(f32::MAX);
+        let mut max = Vec3::splat(f32::MIN);
+        for (mesh, vertices) in base_model.meshes.iter().zip(self.meshes.iter_mut()) {
+            for (base, vertex) in mesh.vertices.iter().zip(vertices.iter_mut()) {
+                vertex.position = transform.transform_point3(base.position);
+                vertex.normal = (rotation * base.normal.truncate())
+                    .normalize_or_zero()
+                    .extend(0.0);
+                vertex.color = multiply(base.color, tint);
+                min = min.min(vertex.position);
+                max = max.max(vertex.position);
+            }
+        }
+        self.center = (min + max) * 0.5;
+        self.radius = (max - min).length() * 0.5;
+    }
+}
+
+/// Colours multiplied channel by channel, white keeps the colour.
+fn multiply(a: [u8; 4], b: [u8; 4]) -> [u8; 4] {
+    [0, 1, 2, 3].map(|i| (a[i] as u16 * b[i] as u16 / 255) as u8)
+}
+
+/// What was sent to the GPU in one frame, shown in the debug overlay.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct DrawStats {
+    /// Meshes drawn, once per mesh and visible tile with all its instances.
+    /// Macroquad can still split or merge these into its own draw calls.
+    pub meshes_drawn: usize,
+    pub vertices: usize,
+    /// Number of instance copies skipped by culling.
+    pub culled: usize,
+}
+
+impl std::ops::AddAssign for DrawStats {
+    fn add_assign(&mut self, other: DrawStats) {
+        self.meshes_drawn += other.meshes_drawn;
+        self.vertices += other.vertices;
+        self.culled += other.culled;
+    }
 }
 
 /** The same models will be drawn multiple times at different
 positions. This struct combines meshes so less data has to be sent
 to GPU. This is used instead of instancing which is not supported by macroquad.
+
+Every instance keeps its own transformed vertices, so adding, moving or
+removing one instance does not touch the others. Repeated copies of the
+world (tiles) are drawn by moving the model matrix, all visible instances
+of a tile are drawn in one batch per texture.
+
+The base model is shared, so it can be replaced when it is reloaded.
 */
-pub struct MultiModel<'a> {
-    base_model: &'a Model3D,
+pub struct MultiModel {
+    base_model: Rc<Model3D>,
     base_transform: Mat4,
-    combined_model: Vec<PartialMesh>,
-    textures: Vec<&'a Texture2D>,
+    /// Multiplied with the vertex colours of the base model.
+    tint: [u8; 4],
     repeat: i32,
-    add_transforms: HashMap<usize, Mat4>,
+    instances: Vec<Instance>,
+    // Position of each id in `instances`
+    index_of: HashMap<usize, usize>,
 }
 
-impl<'a> MultiModel<'a> {
-    pub fn new(base_model: &'a Model3D, repeat: i32) -> MultiModel<'a> {
-        let combined_model: Vec<PartialMesh> = Vec::new();
-        let mut textures = Vec::new();
-        for mesh in &base_model.meshes {
-            textures.push(mesh.texture.as_ref().unwrap());
-        }
+impl MultiModel {
+    pub fn new(base_model: Rc<Model3D>, repeat: i32) -> MultiModel {
         MultiModel {
             base_model,
             base_transform: Mat4::IDENTITY,
-            combined_model,
-            textures,
+            tint: [255; 4],
             repeat,
-            add_transforms: HashMap::new(),
+            instances: Vec::new(),
+            index_of: HashMap::new(),
         }
     }
 
-    fn repeat_mesh(
-        mesh: &Mesh,
-        transform: &Mat4,
-        repeat: i32,
-        texture_id: usize,
-    ) -> Vec<PartialMesh> {
-        let base_vertices: Vec<Vertex> = mesh
-            .vertices
-            .iter()
-            .map(|v| Vertex {
-                position: transform.mul_vec4(Vec4::from((v.position, 1.0))).truncate(),
-                uv: v.uv,
-                color: v.color,
-                normal: v.normal,
-            })
-            .collect();
-        let mut vertices: Vec<Vertex> = Vec::new();
-        let mut indices: Vec<u16> = Vec::new();
-        let mut index_offset = 0;
-        let mut result = Vec::new();
-        for i in -repeat..=repeat {
-            for j in -repeat..=repeat {
-                for k in -repeat..=repeat {
-                    let position = vec3(
-                        i as f32 * SPACE_SIZE,
-                        j as f32 * SPACE_SIZE,
-                        k as f32 * SPACE_SIZE,
-                    );
-                    // There is a limit to geometry call size
-                    if indices.len() + mesh.indices.len() > 5000 {
-                        result.push(PartialMesh {
-                            vertices,
-                            indices,
-                            texture_id,
-                        });
-                        indices = Vec::new();
-                        vertices = Vec::new();
-                        index_offset = 0;
-                    }
-                    for vertex in base_vertices.iter() {
-                        let mut moved_vertex = *vertex;
-                        moved_vertex.position += position;
-                        vertices.push(moved_vertex);
-                    }
-                    for index in mesh.indices.iter() {
-                        indices.push(index + index_offset);
-                    }
-                    index_offset += mesh.vertices.len() as u16;
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Copies of the world drawn in every direction, instances stay as they are.
+    pub fn set_repeat(&mut self, repeat: i32) {
+        self.repeat = repeat;
+    }
+
+    /// Adds an instance, or moves it if one with the same id already exists.
+    pub fn add_transformed(&mut self, transform: &Mat4, id: usize) {
+        match self.index_of.get(&id) {
+            Some(&i) => {
+                let instance = &mut self.instances[i];
+                if instance.transform == *transform {
+                    return; // Not moved, for example when the game is paused
                 }
+                instance.transform = *transform;
+                instance.bake(&self.base_model, self.base_transform, self.tint);
+            }
+            None => {
+                let instance = Instance::new(
+                    id,
+                    &self.base_model,
+                    *transform,
+                    self.base_transform,
+                    self.tint,
+                );
+                self.index_of.insert(id, self.instances.len());
+                self.instances.push(instance);
+            }
+        }
+    }
+
+    pub fn remove_transformed(&mut self, id: usize) {
+        if let Some(i) = self.index_of.remove(&id) {
+            self.instances.swap_remove(i);
+            if let Some(moved) = self.instances.get(i) {
+                self.index_of.insert(moved.id, i);
             }
         }
-        if !indices.is_empty() {
-            result.push(PartialMesh {
-                vertices,
-                indices,
-                texture_id,
-            });
+    }
+
+    /// Transform applied to every instance before its own transform.
+    /// This has to update all instances.
+    pub fn base_transform(&mut self, transform: Mat4) {
+        self.base_transform = transform;
+        for instance in self.instances.iter_mut() {
+            instance.bake(&self.base_model, transform, self.tint);
         }
-        result
     }
 
-    pub fn add_transformed(&mut self, transform: &Mat4, id: usize) {
-        self.add_transforms.insert(id, *transform);
-        for (i, mesh) in self.base_model.meshes.iter().enumerate() {
-            self.combined_model
-                .extend(Self::repeat_mesh(mesh, transform, self.repeat, i));
+    /// Colour multiplied with the colours of the model, for skins.
+    pub fn set_tint(&mut self, tint: Color) {
+        self.tint = tint.into();
+        for instance in self.instances.iter_mut() {
+            instance.bake(&self.base_model, self.base_transform, self.tint);
         }
     }
 
-    /** Lazy removal. Meshes are updated when base_transform is called.
-     */
-    pub fn remove_transformed(&mut self, id: usize) {
-        self.add_transforms.remove(&id);
+    /// Uses `new` instead of the base model `old`, if this is a copy of it.
+    /// Instances stay where they are.
+    pub fn replace_base_model(&mut self, old: &Rc<Model3D>, new: &Rc<Model3D>) {
+        if !Rc::ptr_eq(&self.base_model, old) {
+            return;
+        }
+        self.base_model = new.clone();
+        // The new model can have a different number of meshes and vertices
+        for instance in self.instances.iter_mut() {
+            *instance = Instance::new(
+                instance.id,
+                &self.base_model,
+                instance.transform,
+                self.base_transform,
+                self.tint,
+            );
+        }
     }
 
-    pub fn draw(&self) {
+    pub fn draw(&self, culling: &Culling) -> DrawStats {
         let gl;
         unsafe {
             gl = get_internal_gl().quad_gl;
         }
         gl.draw_mode(DrawMode::Triangles);
 
-        // Sort by texture, so we don't sent too many updates to GPU
-        let mut draw_order: Vec<usize> = (0..self.combined_model.len()).collect();
-        draw_order.sort_by_key(|&i| {
-            self.textures[self.combined_model[i].texture_id] as *const Texture2D as usize
-        });
-        let mut prev_id: usize = 0;
-        for i in draw_order {
-            let mesh = self.combined_model.get(i).unwrap();
-            let texture = self.textures[mesh.texture_id];
-            let texture_ptr = texture as *const Texture2D as usize;
-            if prev_id != texture_ptr {
-                gl.texture(Some(texture));
-                prev_id = texture_ptr;
+        let mut stats = DrawStats::default();
+        let mut visible: Vec<&Instance> = Vec::with_capacity(self.instances.len());
+        let repeat = self.repeat;
+        for i in -repeat..=repeat {
+            for j in -repeat..=repeat {
+                for k in -repeat..=repeat {
+                    let offset = vec3(
+                        i as f32 * SPACE_SIZE,
+                        j as f32 * SPACE_SIZE,
+                        k as f32 * SPACE_SIZE,
+                    );
+                    visible.clear();
+                    visible.extend(
+                        self.instances
+                            .iter()
+                            .filter(|inst| culling.is_visible(inst.center + offset, inst.radius)),
+                    );
+                    stats.culled += self.instances.len() - visible.len();
+                    if visible.is_empty() {
+                        continue;
+                    }
+                    gl.push_model_matrix(Mat4::from_translation(offset));
+                    for (mesh_id, mesh) in self.base_model.meshes.iter().enumerate() {
+                        gl.texture(mesh.texture.as_ref());
+                        stats.meshes_drawn += 1;
+                        for instance in visible.iter() {
+                            let vertices = &instance.meshes[mesh_id];
+                            gl.geometry(vertices, &mesh.indices);
+                            stats.vertices += vertices.len();
+                        }
+                    }
+                    gl.pop_model_matrix();
+                }
             }
-            gl.geometry(&mesh.vertices, &mesh.indices);
         }
+        stats
     }
+}
 
-    pub fn base_transform(&mut self, transform: Mat4) {
-        self.base_transform = transform;
-        self.combined_model.clear();
-        for (_, add_transform) in self.add_transforms.clone() {
-            for (i, mesh) in self.base_model.meshes.iter().enumerate() {
-                self.combined_model.extend(Self::repeat_mesh(
-                    mesh,
-                    &add_transform.mul_mat4(&transform),
-                    self.repeat,
-                    i,
-                ));
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::color::WHITE;
+    use macroquad::models::Mesh;
+
+    fn model() -> MultiModel {
+        let mesh = Mesh {
+            vertices: vec![Vertex::new(0.0, 0.0, 0.0, 0.0, 0.0, WHITE)],
+            indices: vec![0, 0, 0],
+            texture: None,
+        };
+        MultiModel::new(Rc::new(Model3D { meshes: vec![mesh] }), 0)
+    }
+
+    fn at(x: f32) -> Mat4 {
+        Mat4::from_translation(vec3(x, 0.0, 0.0))
+    }
+
+    /// Every id is found at its index and its vertices are where it was put.
+    fn check(model: &MultiModel, expected: &[(usize, f32)]) {
+        assert_eq!(model.len(), expected.len());
+        assert_eq!(model.index_of.len(), expected.len());
+        for &(id, x) in expected {
+            let instance = &model.instances[model.index_of[&id]];
+            assert_eq!(instance.id, id);
+            assert_eq!(instance.transform, at(x));
+            assert_eq!(instance.meshes[0][0].position, vec3(x, 0.0, 0.0));
         }
     }
 
-    pub fn refresh_transformed(&mut self) {
-        self.base_transform(self.base_transform);
+    #[test]
+    fn test_add_move_remove() {
+        let mut model = model();
+        for id in 0..5 {
+            model.add_transformed(&at(id as f32), id);
+        }
+        check(&model, &[(0, 0.0), (1, 1.0), (2, 2.0), (3, 3.0), (4, 4.0)]);
+
+        // Moving keeps the number of instances
+        model.add_transformed(&at(10.0), 1);
+        model.add_transformed(&at(14.0), 4);
+        check(
+            &model,
+            &[(0, 0.0), (1, 10.0), (2, 2.0), (3, 3.0), (4, 14.0)],
+        );
+
+        // In the middle, the last one takes its place
+        model.remove_transformed(1);
+        check(&model, &[(0, 0.0), (2, 2.0), (3, 3.0), (4, 14.0)]);
+        // At the end
+        model.remove_transformed(3);
+        model.remove_transformed(4);
+        check(&model, &[(0, 0.0), (2, 2.0)]);
+        // Unknown ids are ignored
+        model.remove_transformed(4);
+        check(&model, &[(0, 0.0), (2, 2.0)]);
+
+        // A removed id can be added again
+        model.add_transformed(&at(7.0), 1);
+        model.add_transformed(&at(12.0), 2);
+        check(&model, &[(0, 0.0), (1, 7.0), (2, 12.0)]);
+        model.remove_transformed(0);
+        check(&model, &[(1, 7.0), (2, 12.0)]);
     }
 }
@@ -1,44 +1,181 @@
+use crate::draw_utils::SPACE_SIZE;
+use crate::models3d::{far_distance, repeat_for};
+use crate::orientation::Orientation;
+use crate::snake::modulus_vec3;
 use macroquad::prelude::*;
 
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum CameraMode {
+    Chase,
+    FirstPerson,
+    FarChase,
+    /// Free camera circling the head, only available when the game is not running.
+    Orbit,
+}
+
+impl CameraMode {
+    /// Camera modes that can be used while playing, in the order `C` cycles through them.
+    const PLAYABLE: [CameraMode; 3] = [
+        CameraMode::Chase,
+        CameraMode::FirstPerson,
+        CameraMode::FarChase,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CameraMode::Chase => "chase",
+            CameraMode::FirstPerson => "first person",
+            CameraMode::FarChase => "far chase",
+            CameraMode::Orbit => "orbit",
+        }
+    }
+}
+
 pub struct View {
-    rot_mat: Mat3,
+    orientation: Orientation,
     time_rotating: f32, // How long have wasdqe been pressed
+    camera_mode: CameraMode,
+    playing_camera_mode: CameraMode, // Mode to return to after orbiting
+    orbit_yaw: f32,
+    orbit_pitch: f32,
+    orbit_distance: f32,
+    // Camera position and target relative to the head in (forward, up, right) coordinates.
+    // These follow the offsets of the current mode smoothly.
+    eye_offset: Vec3,
+    target_offset: Vec3,
 }
 
 impl View {
+    const CAMERA_SMOOTHING: f32 = 6.0; // Higher is faster
+    const ORBIT_SPEED: f32 = 1.5; // Radians per second
+    const ORBIT_DISTANCE: (f32, f32) = (10.0, 80.0);
+    /// Field of view in Y, same as the default of macroquad.
+    const FOVY: f32 = std::f32::consts::FRAC_PI_4;
+    /// Depth of the far plane, the view ends here when the fog is off.
+    const FAR: f32 = SPACE_SIZE * 3.0;
+
     pub fn new() -> Self {
+        let (eye_offset, target_offset) = (vec3(-5., 5., 0.), vec3(-4., 5., 0.));
         Self {
-            rot_mat: Mat3::IDENTITY,
+            orientation: Orientation::IDENTITY,
             time_rotating: 0.0,
+            camera_mode: CameraMode::Chase,
+            playing_camera_mode: CameraMode::Chase,
+            orbit_yaw: 0.0,
+            orbit_pitch: 0.5,
+            orbit_distance: 30.0,
+            eye_offset,
+            target_offset,
         }
     }
 
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
     pub fn up(&self) -> Vec3 {
-        (self.rot_mat * vec3(0., 1., 0.)).normalize()
+        self.orientation.up()
     }
 
     pub fn forward(&self) -> Vec3 {
-        (self.rot_mat * vec3(1., 0., 0.)).normalize()
+        self.orientation.forward()
     }
 
     pub fn right(&self) -> Vec3 {
-        (self.rot_mat * vec3(0., 0., 1.)).normalize()
+        self.orientation.right()
     }
 
-    pub fn cam_offset(&self) -> Vec3 {
-        self.up() * 5.0 - self.forward() * 5.0
+    pub fn camera_mode(&self) -> CameraMode {
+        self.camera_mode
     }
 
-    pub fn correct(&mut self) {
-        // Correct the rotation matrix to be orthogonal
-        let forward = self.forward();
-        let up = self.up();
+    /// Switches to the next camera mode usable during play.
+    pub fn next_camera_mode(&mut self) {
+        let modes = CameraMode::PLAYABLE;
+        let i = modes
+            .iter()
+            .position(|m| *m == self.playing_camera_mode)
+            .unwrap_or(0);
+        self.playing_camera_mode = modes[(i + 1) % modes.len()];
+        if self.camera_mode != CameraMode::Orbit {
+            self.camera_mode = self.playing_camera_mode;
+        }
+    }
 
-        // Recalculate the right vector
-        let new_right = forward.cross(up).normalize();
-        let new_up = new_right.cross(forward).normalize();
+    /// Turns the spectator orbit camera on or off.
+    pub fn toggle_orbit(&mut self) {
+        if self.camera_mode == CameraMode::Orbit {
+            self.camera_mode = self.playing_camera_mode;
+        } else {
+            self.camera_mode = CameraMode::Orbit;
+        }
+    }
 
-        self.rot_mat = Mat3::from_cols(forward, new_up, new_right);
+    /// Eye and target offsets of the current mode in (forward, up, right) coordinates.
+    fn mode_offsets(&self) -> (Vec3, Vec3) {
+        match self.camera_mode {
+            CameraMode::Chase => (vec3(-5., 5., 0.), vec3(-4., 5., 0.)),
+            CameraMode::FirstPerson => (vec3(4., -1., 0.), vec3(5., -1., 0.)),
+            CameraMode::FarChase => (vec3(-25., 15., 0.), vec3(-24., 14.7, 0.)),
+            CameraMode::Orbit => {
+                let (yaw, pitch) = (self.orbit_yaw, self.orbit_pitch);
+                let eye = vec3(
+                    -pitch.cos() * yaw.cos(),
+                    pitch.sin(),
+                    pitch.cos() * yaw.sin(),
+                ) * self.orbit_distance;
+                (eye, Vec3::ZERO)
+            }
+        }
+    }
+
+    /// Furthest the camera sees: the corners of the far plane, or the fog if it is closer.
+    pub fn view_distance(&self, fog: Option<f32>) -> f32 {
+        let aspect = screen_width() / screen_height().max(1.0);
+        let far = far_distance(Self::FOVY, aspect, Self::FAR);
+        fog.map_or(far, |fog| fog.min(far))
+    }
+
+    /// Copies of the world the models need in every direction to fill the view.
+    pub fn repeat(&self, fog: Option<f32>) -> i32 {
+        repeat_for(self.view_distance(fog))
+    }
+
+    fn local_to_world(&self, offset: Vec3) -> Vec3 {
+        self.forward() * offset.x + self.up() * offset.y + self.right() * offset.z
+    }
+
+    /// Arrow keys circle around the head, mouse wheel zooms.
+    pub fn orbit(&mut self, dt: f32) {
+        if self.camera_mode != CameraMode::Orbit {
+            return;
+        }
+        if is_key_down(KeyCode::Left) {
+            self.orbit_yaw -= Self::ORBIT_SPEED * dt;
+        }
+        if is_key_down(KeyCode::Right) {
+            self.orbit_yaw += Self::ORBIT_SPEED * dt;
+        }
+        if is_key_down(KeyCode::Up) {
+            self.orbit_pitch += Self::ORBIT_SPEED * dt;
+        }
+        if is_key_down(KeyCode::Down) {
+            self.orbit_pitch -= Self::ORBIT_SPEED * dt;
+        }
+        // Stay away from the poles, camera up would be parallel to the view direction
+        self.orbit_pitch = self.orbit_pitch.clamp(-1.4, 1.4);
+
+        let (_, wheel) = mouse_wheel();
+        self.orbit_distance = (self.orbit_distance - wheel.clamp(-1., 1.) * 2.)
+            .clamp(Self::ORBIT_DISTANCE.0, Self::ORBIT_DISTANCE.1);
+    }
+
+    /// Moves the camera towards the offsets of the current mode.
+    pub fn update_camera(&mut self, dt: f32) {
+        let (eye, target) = self.mode_offsets();
+        let k = 1.0 - (-Self::CAMERA_SMOOTHING * dt).exp();
+        self.eye_offset = self.eye_offset.lerp(eye, k);
+        self.target_offset = self.target_offset.lerp(target, k);
     }
 
     pub fn rotate(&mut self, dt: f32) {
@@ -50,27 +187,24 @@ impl View {
         let rot_speed = rot_speed.min(10.0); // Limit the rotation speed
 
         if is_key_down(KeyCode::E) {
-            self.rot_mat = Mat3::from_axis_angle(dir, rot_speed) * self.rot_mat;
+            self.orientation.rotate(dir, rot_speed);
         }
         if is_key_down(KeyCode::Q) {
-            self.rot_mat = Mat3::from_axis_angle(dir, -rot_speed) * self.rot_mat;
+            self.orientation.rotate(dir, -rot_speed);
         }
         if is_key_down(KeyCode::A) {
-            self.rot_mat = Mat3::from_axis_angle(up, rot_speed) * self.rot_mat;
+            self.orientation.rotate(up, rot_speed);
         }
         if is_key_down(KeyCode::D) {
-            self.rot_mat = Mat3::from_axis_angle(up, -rot_speed) * self.rot_mat;
+            self.orientation.rotate(up, -rot_speed);
         }
         if is_key_down(KeyCode::S) {
-            self.rot_mat = Mat3::from_axis_angle(right, -rot_speed) * self.rot_mat;
+            self.orientation.rotate(right, -rot_speed);
         }
         if is_key_down(KeyCode::W) {
-            self.rot_mat = Mat3::from_axis_angle(right, rot_speed) * self.rot_mat;
+            self.orientation.rotate(right, rot_speed);
         }
 
-        // Correct the rotation matrix to be orthogonal
-        self.correct();
-
         if is_key_down(KeyCode::E)
             || is_key_down(KeyCode::Q)
             || is_key_down(KeyCode::A)
@@ -84,18 +218,80 @@ impl View {
         }
     }
 
-    pub fn set_camera(&self, player_pos: Vec3) {
-        let cam_offset = self.cam_offset();
-        set_camera(&Camera3D {
-            position: player_pos + cam_offset,
+    /// Camera following `player_pos`, it is not moved into the base cube.
+    pub fn camera_at(&self, player_pos: Vec3) -> Camera3D {
+        Camera3D {
+            position: player_pos + self.local_to_world(self.eye_offset),
             up: self.up(),
-            target: player_pos + self.forward() + cam_offset,
+            target: player_pos + self.local_to_world(self.target_offset),
+            fovy: Self::FOVY,
+            z_far: Self::FAR,
             ..Default::default()
-        });
+        }
+    }
+
+    /// Camera following `player_pos`, moved into the base cube.
+    pub fn camera(&self, player_pos: Vec3) -> Camera3D {
+        let camera = self.camera_at(player_pos);
+        let (position, target) = wrap_camera(camera.position, camera.target);
+        Camera3D {
+            position,
+            target,
+            ..camera
+        }
     }
 
     pub fn reset(&mut self) {
-        self.rot_mat = Mat3::IDENTITY;
+        self.orientation = Orientation::IDENTITY;
         self.time_rotating = 0.0;
+        if self.camera_mode == CameraMode::Orbit {
+            self.camera_mode = self.playing_camera_mode;
+        }
+    }
+}
+
+/// Shifts the camera (and its target) by whole multiples of SPACE_SIZE, so it is inside
+/// the base cube. Space wraps around, so the picture is the same, but the repeated copies
+/// are then spread evenly around the camera, even when it is far from the head.
+pub fn wrap_camera(position: Vec3, target: Vec3) -> (Vec3, Vec3) {
+    let shift = position - modulus_vec3(position, SPACE_SIZE);
+    (position - shift, target - shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn almost_eq(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-3
+    }
+
+    #[test]
+    fn test_wrap_camera() {
+        let (p, t) = wrap_camera(vec3(-5., 30., 65.), vec3(-4., 31., 64.));
+        assert!(almost_eq(p, vec3(55., 30., 5.)));
+        assert!(almost_eq(t - p, vec3(1., 1., -1.)));
+
+        let (p, t) = wrap_camera(vec3(10., 20., 30.), vec3(0., 0., 0.));
+        assert!(almost_eq(p, vec3(10., 20., 30.)));
+        assert!(almost_eq(t, vec3(0., 0., 0.)));
+    }
+
+    #[test]
+    fn test_camera_modes_cycle() {
+        let mut view = View::new();
+        assert_eq!(view.camera_mode(), CameraMode::Chase);
+        view.next_camera_mode();
+        assert_eq!(view.camera_mode(), CameraMode::FirstPerson);
+
+        // Orbit remembers the mode to go back to
+        view.toggle_orbit();
+        assert_eq!(view.camera_mode(), CameraMode::Orbit);
+        view.next_camera_mode();
+        assert_eq!(view.camera_mode(), CameraMode::Orbit);
+        view.toggle_orbit();
+        assert_eq!(view.camera_mode(), CameraMode::FarChase);
+        view.next_camera_mode();
+        assert_eq!(view.camera_mode(), CameraMode::Chase);
     }
 }
@@ -0,0 +1,176 @@
+use macroquad::prelude::*;
+
+/** Rotation of an object in space, stored as a unit quaternion.
+
+Local axes are the same for everything in the game: forward is +X,
+up is +Y and right is +Z, so `right = forward x up` (right-handed).
+Models are expected to be authored with the same axes.
+*/
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Orientation {
+    rotation: Quat,
+}
+
+impl Orientation {
+    pub const IDENTITY: Orientation = Orientation {
+        rotation: Quat::IDENTITY,
+    };
+
+    pub fn from_quat(rotation: Quat) -> Self {
+        Self {
+            rotation: rotation.normalize(),
+        }
+    }
+
+    /// Builds an orientation looking along `forward`. `up` does not have to be
+    /// perpendicular to `forward`, only its component perpendicular to `forward` is used.
+    pub fn from_forward_up(forward: Vec3, up: Vec3) -> Self {
+        let forward = forward.normalize();
+        let right = forward.cross(up).normalize();
+        let up = right.cross(forward);
+        Self::from_quat(Quat::from_mat3(&Mat3::from_cols(forward, up, right)))
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.rotation * Vec3::X
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.rotation * Vec3::Y
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.rotation * Vec3::Z
+    }
+
+    /// Rotation matrix with columns (forward, up, right).
+    pub fn to_mat3(self) -> Mat3 {
+        Mat3::from_quat(self.rotation)
+    }
+
+    /// Model matrix placing an object with this orientation at `position`.
+    pub fn to_mat4(self, position: Vec3) -> Mat4 {
+        Mat4::from_translation(position).mul_mat4(&Mat4::from_mat3(self.to_mat3()))
+    }
+
+    /// Rotates around an axis given in world space.
+    pub fn rotate(&mut self, axis: Vec3, angle: f32) {
+        self.rotation = Quat::from_axis_angle(axis.normalize(), angle) * self.rotation;
+        self.normalize();
+    }
+
+    /// Removes accumulated floating point error.
+    pub fn normalize(&mut self) {
+        self.rotation = self.rotation.normalize();
+    }
+
+    /// Spherical interpolation, `t = 0` gives `self` and `t = 1` gives `other`.
+    pub fn slerp(&self, other: &Orientation, t: f32) -> Orientation {
+        Self::from_quat(self.rotation.slerp(other.rotation, t))
+    }
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn almost_eq(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-3
+    }
+
+    fn assert_orthonormal(o: &Orientation) {
+        let (f, u, r) = (o.forward(), o.up(), o.right());
+        assert!((f.length() - 1.0).abs() < 1e-3);
+        assert!((u.length() - 1.0).abs() < 1e-3);
+        assert!((r.length() - 1.0).abs() < 1e-3);
+        assert!(f.dot(u).abs() < 1e-3);
+        assert!(f.dot(r).abs() < 1e-3);
+        assert!(u.dot(r).abs() < 1e-3);
+        // right-handed
+        assert!(almost_eq(f.cross(u), r));
+    }
+
+    #[test]
+    fn test_identity_axes() {
+        let o = Orientation::IDENTITY;
+        assert!(almost_eq(o.forward(), Vec3::X));
+        assert!(almost_eq(o.up(), Vec3::Y));
+        assert!(almost_eq(o.right(), Vec3::Z));
+        assert!((o.to_mat3().determinant() - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_from_forward_up() {
+        let o = Orientation::from_forward_up(vec3(1., 0., 0.), vec3(0., 0., 1.));
+        assert!(almost_eq(o.forward(), Vec3::X));
+        assert!(almost_eq(o.up(), Vec3::Z));
+        assert!(almost_eq(o.right(), -Vec3::Y));
+        assert_orthonormal(&o);
+
+        // up that is not perpendicular gets corrected
+        let o = Orientation::from_forward_up(vec3(0., 2., 0.), vec3(0., 1., 1.));
+        assert!(almost_eq(o.forward(), Vec3::Y));
+        assert!(almost_eq(o.up(), Vec3::Z));
+        assert_orthonormal(&o);
+    }
+
+    #[test]
+    fn test_matrix_columns() {
+        let o = Orientation::from_forward_up(vec3(0., 1., 1.), vec3(1., 0., 0.));
+        let m = o.to_mat3();
+        assert!(almost_eq(m.x_axis, o.forward()));
+        assert!(almost_eq(m.y_axis, o.up()));
+        assert!(almost_eq(m.z_axis, o.right()));
+        assert!((m.determinant() - 1.0).abs() < 1e-3);
+
+        let m4 = o.to_mat4(vec3(1., 2., 3.));
+        assert!(almost_eq(m4.transform_point3(Vec3::ZERO), vec3(1., 2., 3.)));
+        assert!(almost_eq(
+            m4.transform_point3(Vec3::X),
+            vec3(1., 2., 3.) + o.forward()
+        ));
+    }
+
+    #[test]
+    fn test_rotate_direction() {
+        // Turning left around up moves forward towards -right
+        let mut o = Orientation::IDENTITY;
+        o.rotate(o.up(), std::f32::consts::FRAC_PI_2);
+        assert!(almost_eq(o.forward(), -Vec3::Z));
+        assert!(almost_eq(o.right(), Vec3::X));
+        assert_orthonormal(&o);
+    }
+
+    #[test]
+    fn test_no_drift() {
+        let mut o = Orientation::IDENTITY;
+        for i in 0..100_000 {
+            let axis = match i % 3 {
+                0 => o.forward(),
+                1 => o.up(),
+                _ => o.right(),
+            };
+            o.rotate(axis, 0.0137);
+        }
+        assert!((o.to_mat3().determinant() - 1.0).abs() < 1e-4);
+        assert_orthonormal(&o);
+    }
+
+    #[test]
+    fn test_slerp() {
+        let a = Orientation::IDENTITY;
+        let b = Orientation::from_forward_up(Vec3::Z, Vec3::Y);
+        assert!(almost_eq(a.slerp(&b, 0.0).forward(), a.forward()));
+        assert!(almost_eq(b.forward(), a.slerp(&b, 1.0).forward()));
+        let half = a.slerp(&b, 0.5);
+        assert!(almost_eq(half.forward(), vec3(1., 0., 1.).normalize()));
+        assert!(almost_eq(half.up(), Vec3::Y));
+        assert_orthonormal(&half);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::achievements::AchievementProgress;
+use crate::assets::{AssetError, LoadReport};
+use crate::settings::Settings;
+use crate::skins::SkinChoice;
+use crate::stats::LifetimeStats;
+use crate::storage::{load_toml, save_toml};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/** The players sharing the machine. Every profile keeps its settings, skin,
+stats and achievements in its own directory `profiles/<name>/` in the data
+directory, the names and the profile that was used last are saved in
+`profiles.toml` there.
+*/
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profiles {
+    pub names: Vec<String>,
+    pub current: String,
+    /// The data directory, see `storage::data_dir`.
+    #[serde(skip)]
+    data_dir: PathBuf,
+}
+
+impl Profiles {
+    const FILE: &'static str = "profiles.toml";
+    const DIR: &'static str = "profiles";
+    pub const MAX_NAME_LENGTH: usize = 12;
+    /// Saved directly in the data directory before there were profiles, moved to the first profile.
+    const OLD_FILES: [&'static str; 4] = [
+        "settings.toml",
+        "skin.txt",
+        "stats.toml",
+        "achievements.toml",
+    ];
+
+    /// A directory that can't be written is added to `report`, the profiles
+    /// are then only kept while the game runs.
+    pub fn load(data_dir: &Path, report: &mut LoadReport) -> Self {
+        let mut profiles: Self = load_toml(&data_dir.join(Self::FILE));
+        profiles.data_dir = data_dir.to_path_buf();
+        if profiles.names.is_empty() {
+            profiles.names.push(String::from("Player"));
+            profiles.current = String::from("Player");
+            profiles.move_old_files();
+            if let Err(error) = profiles.save() {
+                report.push(AssetError::File {
+                    path: data_dir.join(Self::FILE).display().to_string(),
+                    message: error.to_string(),
+                });
+            }
+        }
+        if !profiles.names.contains(&profiles.current) {
+            profiles.current = profiles.names[0].clone();
+        }
+        profiles
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        save_toml(&self.data_dir.join(Self::FILE), self)
+    }
+
+    fn move_old_files(&self) {
+        let dir = self.current_dir();
+        if fs::create_dir_all(&dir).is_err() {
+            return; // The files stay where they were
+        }
+        for file in Self::OLD_FILES {
+            let old = self.data_dir.join(file);
+            if old.exists() {
+                // Nothing is lost if it fails, the old file stays where it was
+                let _ = fs::rename(&old, dir.join(file));
+            }
+        }
+    }
+
+    pub fn current_dir(&self) -> PathBuf {
+        self.data_dir.join(Self::DIR).join(&self.current)
+    }
+
+    /// Why `name` can't be used for a new profile.
+    pub fn check_name(&self, name: &str) -> Result<(), &'static str> {
+        if name.trim().is_empty() {
+            return Err("type a name");
+        }
+        if self
+            .names
+            .iter()
+            .any(|other| other.eq_ignore_ascii_case(name.trim()))
+        {
+            return Err("that name is taken");
+        }
+        Ok(())
+    }
+
+    /// Adds the profile and switches to it, it still has to be saved.
+    pub fn add(&mut self, name: &str) -> Result<(), &'static str> {
+        self.check_name(name)?;
+        self.names.push(name.trim().to_string());
+        self.current = name.trim().to_string();
+        Ok(())
+    }
+}
+
+/// Everything that is saved per profile.
+pub struct ProfileData {
+    pub dir: PathBuf,
+    pub settings: Settings,
+    pub skin: SkinChoice,
+    pub stats: LifetimeStats,
+    pub achievements: AchievementProgress,
+}
+
+impl ProfileData {
+    pub fn load(dir: PathBuf) -> Self {
+        Self {
+            settings: Settings::load(&dir),
+            skin: SkinChoice::load(&dir),
+            stats: LifetimeStats::load(&dir),
+            achievements: AchievementProgress::load(&dir),
+            dir,
+        }
+    }
+}
+
+/// A name typed on the keyboard, for profiles and high scores.
+pub struct NameEntry {
+    pub text: String,
+}
+
+impl NameEntry {
+    pub fn new(text: &str) -> Self {
+        // Letters typed while playing are still queued
+        while get_char_pressed().is_some() {}
+        Self {
+            text: text.to_string(),
+        }
+    }
+
+    /// Returns true when Enter was pressed.
+    pub fn update(&mut self) -> bool {
+        while let Some(c) = get_char_pressed() {
+            self.type_char(c);
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            self.text.pop();
+        }
+        is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::KpEnter)
+    }
+
+    /// Names are also directory names, so only letters, digits, spaces, `-` and `_`.
+    fn type_char(&mut self, c: char) {
+        let allowed = c.is_alphanumeric() || c == ' ' || c == '-' || c == '_';
+        if allowed && self.text.chars().count() < Profiles::MAX_NAME_LENGTH {
+            self.text.push(c);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_name() {
+        let profiles = Profiles {
+            names: vec![String::from("Anna")],
+            current: String::from("Anna"),
+            ..Default::default()
+        };
+        assert!(profiles.check_name("Bob").is_ok());
+        assert!(profiles.check_name(" anna ").is_err());
+        assert!(profiles.check_name("  ").is_err());
+    }
+
+    #[test]
+    fn test_type_char() {
+        let mut entry = NameEntry {
+            text: String::new(),
+        };
+        for c in "Jo/\\.n\r_1 2345678901".chars() {
+            entry.type_char(c);
+        }
+        assert_eq!(entry.text, "Jon_1 234567");
+    }
+}
@@ -0,0 +1,194 @@
+use crate::draw_utils::SPACE_SIZE;
+use crate::food::{FoodFactory, FoodTarget, FoodVariant};
+use crate::movement::View;
+use crate::snake::{mod_delta, Shnek};
+use macroquad::prelude::*;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum RadarMode {
+    Off,
+    /// Looking down along the view up vector, forward is up on the screen.
+    TopDown,
+    /// Small 3D picture of the cube, seen from behind and above the head.
+    Rotating3D,
+}
+
+/** HUD radar of the wrapped cube. Everything is shown relative to the head,
+which is always in the middle of the radar, using the shortest wrapped path,
+so the radar shows the real food and not one of the repeated copies.
+*/
+pub struct Radar {
+    pub mode: RadarMode,
+}
+
+impl Radar {
+    const SIZE: f32 = 220.0; // Size of the radar on screen in pixels
+    const MARGIN: f32 = 20.0;
+    const TILT: f32 = 0.5; // How much the 3D mode looks from above, in radians
+
+    pub fn new() -> Self {
+        Self {
+            mode: RadarMode::Rotating3D,
+        }
+    }
+
+    pub fn next_mode(&mut self) {
+        self.mode = match self.mode {
+            RadarMode::Off => RadarMode::TopDown,
+            RadarMode::TopDown => RadarMode::Rotating3D,
+            RadarMode::Rotating3D => RadarMode::Off,
+        };
+    }
+
+    /// Projects a wrapped delta from the head to radar coordinates in [-1, 1]^2
+    /// (y pointing down, like the screen). The third component is depth,
+    /// bigger is further away from the viewer.
+    pub fn project(&self, delta: Vec3, view: &View) -> Vec3 {
+        // Coordinates in the view frame, scaled so the cube is [-1, 1]^3
+        let local = vec3(
+            delta.dot(view.right()),
+            delta.dot(view.up()),
+            delta.dot(view.forward()),
+        ) / (SPACE_SIZE * 0.5);
+        match self.mode {
+            RadarMode::TopDown => vec3(local.x, -local.z, -local.y),
+            _ => {
+                let (sin, cos) = Self::TILT.sin_cos();
+                let y = local.y * cos + local.z * sin;
+                let depth = local.z * cos - local.y * sin;
+                // Leave some space, the rotated cube is bigger than [-1, 1]^2
+                vec3(local.x, -y, depth) * 0.6
+            }
+        }
+    }
+
+    fn to_screen(&self, p: Vec3, center: Vec2) -> Vec2 {
+        center + vec2(p.x, p.y) * Self::SIZE * 0.5
+    }
+
+    fn food_color(variant: FoodVariant) -> Color {
+        match variant {
+            FoodVariant::Normal => RED,
+            FoodVariant::Bad => PURPLE,
+            FoodVariant::Poop => BROWN,
+        }
+    }
+
+    fn draw_cube(&self, view: &View, center: Vec2) {
+        let half = SPACE_SIZE * 0.5;
+        let corner = |i: usize| {
+            vec3(
+                if i & 1 == 0 { -half } else { half },
+                if i & 2 == 0 { -half } else { half },
+                if i & 4 == 0 { -half } else { half },
+            )
+        };
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                let j = i | bit;
+                if j == i {
+                    continue;
+                }
+                let a = self.to_screen(self.project(corner(i), view), center);
+                let b = self.to_screen(self.project(corner(j), view), center);
+                draw_line(a.x, a.y, b.x, b.y, 1.0, Color::new(0., 0., 0., 0.6));
+            }
+        }
+    }
+
+    /// Draws the radar, `nearest_food` is highlighted.
+    pub fn draw(
+        &self,
+        view: &View,
+        player: &Shnek,
+        food_factory: &FoodFactory,
+        nearest_food: Option<FoodTarget>,
+    ) {
+        if self.mode == RadarMode::Off {
+            return;
+        }
+        let center = vec2(
+            screen_width() - Self::SIZE * 0.5 - Self::MARGIN,
+            Self::SIZE * 0.5 + Self::MARGIN,
+        );
+        draw_rectangle(
+            center.x - Self::SIZE * 0.5,
+            center.y - Self::SIZE * 0.5,
+            Self::SIZE,
+            Self::SIZE,
+            Color::new(1., 1., 1., 0.3),
+        );
+        self.draw_cube(view, center);
+
+        let head = player.get_position();
+        // Far things are drawn first and smaller
+        let mut points: Vec<(Vec3, Color, f32, bool)> = Vec::new();
+        for position in player.segment_positions() {
+            let p = self.project(mod_delta(head, position), view);
+            points.push((p, DARKGREEN, 3.0, false));
+        }
+        let target_id = nearest_food.map(|f| f.id);
+        for (id, position, variant) in food_factory.food_positions() {
+            let p = self.project(mod_delta(head, position), view);
+            let is_target = variant == FoodVariant::Normal && Some(id) == target_id;
+            points.push((p, Self::food_color(variant), 5.0, is_target));
+        }
+        points.push((self.project(Vec3::ZERO, view), GREEN, 6.0, false));
+        points.sort_by(|a, b| b.0.z.total_cmp(&a.0.z));
+        for (p, color, size, is_target) in points {
+            let s = self.to_screen(p, center);
+            let size = if self.mode == RadarMode::Rotating3D {
+                size * (1.0 - p.z * 0.3)
+            } else {
+                size
+            };
+            draw_circle(s.x, s.y, size, color);
+            if is_target {
+                draw_circle_lines(s.x, s.y, size + 3.0, 2.0, GOLD);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn almost_eq(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-3
+    }
+
+    #[test]
+    fn test_top_down_projection() {
+        let view = View::new();
+        let radar = Radar {
+            mode: RadarMode::TopDown,
+        };
+        // forward is up on the screen, right is right
+        let p = radar.project(view.forward() * SPACE_SIZE * 0.5, &view);
+        assert!(almost_eq(p, vec3(0., -1., 0.)));
+        let p = radar.project(view.right() * SPACE_SIZE * 0.25, &view);
+        assert!(almost_eq(p, vec3(0.5, 0., 0.)));
+        // up only changes depth
+        let p = radar.project(view.up() * SPACE_SIZE * 0.5, &view);
+        assert!(almost_eq(p, vec3(0., 0., -1.)));
+    }
+
+    #[test]
+    fn test_3d_projection_stays_inside() {
+        let view = View::new();
+        let radar = Radar::new();
+        let half = SPACE_SIZE * 0.5;
+        for x in [-half, half] {
+            for y in [-half, half] {
+                for z in [-half, half] {
+                    let p = radar.project(vec3(x, y, z), &view);
+                    assert!(p.x.abs() <= 1.0 && p.y.abs() <= 1.0);
+                }
+            }
+        }
+        // Things in front are further away and higher on the screen
+        let p = radar.project(view.forward() * 10.0, &view);
+        assert!(p.z > 0.0 && p.y < 0.0);
+    }
+}
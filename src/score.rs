@@ -1,49 +1,238 @@
-use crate::GameState;
-use crate::GameState::MainMenu;
 use std::fs::OpenOptions;
 use std::fs::{self};
-use std::io::Write;
+use std::io::{self, Write};
 // brings `write!` and `writeln!`
+use crate::summary::RunSummary;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The only game mode so far, also the mode of scores from before there were modes.
+pub const DEFAULT_MODE: &str = "classic";
+
+/// One finished game in the score file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreEntry {
+    pub score: i32,
+    /// Empty for scores from before there were names.
+    pub name: String,
+    /// Seconds since 1970, 0 if unknown.
+    pub time: u64,
+    /// Longest the snake got, 0 if unknown.
+    pub length: usize,
+    /// Seconds the game lasted, 0 if unknown.
+    pub duration: u32,
+    pub mode: String,
+}
+
+impl ScoreEntry {
+    pub fn new(score: i32, name: &str, run: &RunSummary) -> Self {
+        Self {
+            score,
+            name: name.to_string(),
+            time: now(),
+            length: run.max_length,
+            duration: run.time_alive as u32,
+            mode: String::from(DEFAULT_MODE),
+        }
+    }
+
+    /// A line of the score file: the score, the name, the time, the length, the
+    /// duration and the mode separated by tabs. Older files only have the first ones.
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.trim().split('\t');
+        let score = fields.next()?.trim().parse::<i32>().ok()?;
+        let name = fields.next().unwrap_or("").to_string();
+        let mut number = || fields.next().and_then(|field| field.parse().ok());
+        let time = number().unwrap_or(0);
+        let length = number().unwrap_or(0) as usize;
+        let duration = number().unwrap_or(0) as u32;
+        let mode = match fields.next() {
+            Some(mode) if !mode.is_empty() => mode.to_string(),
+            _ => String::from(DEFAULT_MODE),
+        };
+        Some(Self {
+            score,
+            name,
+            time,
+            length,
+            duration,
+            mode,
+        })
+    }
+
+    fn line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.score, self.name, self.time, self.length, self.duration, self.mode
+        )
+    }
+}
 
 pub struct Score {
-    file: String,
+    file: PathBuf,
     do_write: bool,
-    pub prev_game_state: GameState,
+    /// When the game was started, for the scores of this session.
+    pub session_start: u64,
+    /// Written at the end of the last game, highlighted on the score screen.
+    pub last: Option<ScoreEntry>,
 }
 
 impl Score {
-    pub fn new() -> Self {
+    /// How many scores the score screen lists on a page, better games ask for a name.
+    pub const TOP: usize = 10;
+
+    /// Scores of all players, in `scores.txt` in the data directory.
+    pub fn new(data_dir: &Path) -> Self {
         Self {
-            file: String::from("assets/scores.txt"),
+            file: data_dir.join("scores.txt"),
             do_write: true,
-            prev_game_state: MainMenu,
+            session_start: now(),
+            last: None,
         }
     }
 
-    pub fn write(&mut self, score: usize) {
-        if self.do_write && score > 0 {
-            let mut write_file = OpenOptions::new()
-                //append ro write
-                .create(true)
-                .append(true)
-                .open(self.file.clone())
-                .expect("Failed to open file for appending");
-
-            write!(write_file, "\n{}", score).expect("Failed to append");
-            // write!(write_file, "\n{}", previous).expect("Failed to append");
+    /// Appends the score once per game. The game is counted as written even
+    /// if it fails, so the error is only reported once.
+    pub fn write(&mut self, score: usize, name: &str, run: &RunSummary) -> io::Result<()> {
+        if !self.do_write || score == 0 {
+            return Ok(());
+        }
+        self.do_write = false;
+        let entry = ScoreEntry::new(score as i32, name, run);
+        self.last = Some(entry.clone());
 
-            self.do_write = false;
+        // Missing on the first start
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir)?;
         }
+        let mut write_file = OpenOptions::new()
+            //append ro write
+            .create(true)
+            .append(true)
+            .open(&self.file)?;
+        write!(write_file, "\n{}", entry.line())
     }
 
     pub fn reset(&mut self) {
         self.do_write = true;
     }
 
-    pub fn read(&self) -> String {
-        let content = fs::read_to_string(self.file.clone()).unwrap_or_else(|_| String::new());
-        let mut scores = content.split('\n').collect::<Vec<&str>>();
-        scores.reverse();
-        scores.join("\n")
+    /// Best score that was saved, 0 if there is none yet.
+    pub fn best(&self) -> i32 {
+        self.entries()
+            .iter()
+            .map(|entry| entry.score)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Whether `score` would be on the first page of the score screen.
+    pub fn is_top(&self, score: i32) -> bool {
+        let top = top(self.entries(), Self::TOP);
+        score > 0 && (top.len() < Self::TOP || top.iter().any(|entry| score > entry.score))
+    }
+
+    /// All scores in the order they were written.
+    pub fn entries(&self) -> Vec<ScoreEntry> {
+        fs::read_to_string(&self.file)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(ScoreEntry::parse)
+            .collect()
+    }
+}
+
+/// The best `n` entries, older ones first when the scores are equal.
+fn top(mut entries: Vec<ScoreEntry>, n: usize) -> Vec<ScoreEntry> {
+    sort_best_first(&mut entries);
+    entries.truncate(n);
+    entries
+}
+
+pub fn sort_best_first(entries: &mut [ScoreEntry]) {
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.score));
+}
+
+/// Seconds since 1970.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// Day of a score like 2025-03-07 (UTC), empty if unknown.
+pub fn format_date(time: u64) -> String {
+    if time == 0 {
+        return String::new();
+    }
+    // Days to a civil date, from Howard Hinnant's date algorithms
+    let days = (time / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let old = ScoreEntry::parse("12").unwrap();
+        assert_eq!((old.score, old.name.as_str(), old.time), (12, "", 0));
+        assert_eq!(old.mode, DEFAULT_MODE);
+        let named = ScoreEntry::parse("30\tAnna Lee\t1700000000").unwrap();
+        assert_eq!(named.name, "Anna Lee");
+        assert_eq!(named.time, 1700000000);
+        assert_eq!(named.length, 0);
+        assert!(ScoreEntry::parse("").is_none());
+
+        let entry = ScoreEntry {
+            score: 7,
+            name: String::from("Bo"),
+            time: 1700000000,
+            length: 10,
+            duration: 95,
+            mode: String::from("classic"),
+        };
+        assert_eq!(ScoreEntry::parse(&entry.line()), Some(entry));
+    }
+
+    #[test]
+    fn test_top() {
+        let entries = [5, 9, 1, 9]
+            .iter()
+            .enumerate()
+            .map(|(i, score)| ScoreEntry {
+                score: *score,
+                name: i.to_string(),
+                time: 0,
+                length: 0,
+                duration: 0,
+                mode: String::from(DEFAULT_MODE),
+            })
+            .collect();
+        let best = top(entries, 3);
+        let names: Vec<&str> = best.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["1", "3", "0"]);
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0), "");
+        assert_eq!(format_date(86400), "1970-01-02");
+        assert_eq!(format_date(1700000000), "2023-11-14");
+        assert_eq!(format_date(1709164800), "2024-02-29");
     }
 }
@@ -0,0 +1,44 @@
+use crate::audio::AudioSettings;
+use crate::storage::{load_toml, save_toml};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+/** What the player changed in the game, saved in `settings.toml` in the profile directory.
+Missing or broken files give the defaults, settings are not worth an error screen.
+*/
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub audio: AudioSettings,
+}
+
+impl Settings {
+    const FILE: &'static str = "settings.toml";
+
+    pub fn load(dir: &Path) -> Self {
+        load_toml(&dir.join(Self::FILE))
+    }
+
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        save_toml(&dir.join(Self::FILE), self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut settings = Settings::default();
+        settings.audio.music = 0.25;
+        settings.audio.muted = true;
+        let text = toml::to_string(&settings).unwrap();
+        assert_eq!(toml::from_str::<Settings>(&text).unwrap(), settings);
+        // Settings from older versions miss fields
+        let old: Settings = toml::from_str("[audio]\nmaster = 0.5\n").unwrap();
+        assert_eq!(old.audio.master, 0.5);
+        assert_eq!(old.audio.sfx, 1.0);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::manifest::{AssetLibrary, SkinEntry};
+use crate::models3d::{Culling, Model3D, MultiModel};
+use crate::snake::Shnek;
+use crate::storage::write_file;
+use macroquad::prelude::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/** The skin the player picked, saved in `skin.txt` in the profile directory.
+Skins are listed in the manifest, a skin that is missing or not unlocked
+(the scores were deleted) falls back to the first one.
+*/
+pub struct SkinChoice {
+    file: PathBuf,
+    pub id: String,
+}
+
+impl SkinChoice {
+    pub fn load(dir: &Path) -> Self {
+        let file = dir.join("skin.txt");
+        let id = fs::read_to_string(&file)
+            .map(|id| id.trim().to_string())
+            .unwrap_or_default();
+        Self { file, id }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        write_file(&self.file, &self.id)
+    }
+
+    /// Position of the chosen skin in `skins`, if it can be used with the best score.
+    pub fn index(&self, skins: &[(String, SkinEntry)], best_score: i32) -> usize {
+        skins
+            .iter()
+            .position(|(id, skin)| *id == self.id && is_unlocked(skin, best_score))
+            .unwrap_or(0)
+    }
+
+    /// Puts the chosen skin on the snake.
+    pub fn apply(&self, player: &mut Shnek, library: &AssetLibrary, best_score: i32) {
+        let skins = library.skins();
+        let (_, skin) = &skins[self.index(&skins, best_score)];
+        let (head, body) = library.skin_models(skin);
+        player.set_skin(head, body, skin.tint_color());
+    }
+}
+
+pub fn is_unlocked(skin: &SkinEntry, best_score: i32) -> bool {
+    best_score >= skin.unlock_score
+}
+
+/// A short snake turning around, shown in the skin menu.
+pub struct SkinPreview {
+    head: MultiModel,
+    body: MultiModel,
+    angle: f32,
+}
+
+impl SkinPreview {
+    const SEGMENTS: usize = 4;
+    const SPACING: f32 = 10.0; // Same as the snake in the game
+
+    pub fn new(library: &AssetLibrary, skin: &SkinEntry) -> Self {
+        let (head_model, body_model) = library.skin_models(skin);
+        // Only one copy, the preview is not in the repeated world
+        let mut head = MultiModel::new(head_model, 0);
+        let mut body = MultiModel::new(body_model, 0);
+        head.set_tint(skin.tint_color());
+        body.set_tint(skin.tint_color());
+        head.add_transformed(&Mat4::IDENTITY, 0);
+        for i in 0..Self::SEGMENTS {
+            let offset = vec3(-Self::SPACING * (i + 1) as f32, 0.0, 0.0);
+            body.add_transformed(&Mat4::from_translation(offset), i);
+        }
+        Self {
+            head,
+            body,
+            angle: 0.0,
+        }
+    }
+
+    /// Switches to a reloaded model, see `MultiModel::replace_base_model`.
+    pub fn replace_model(&mut self, old: &Rc<Model3D>, new: &Rc<Model3D>) {
+        self.head.replace_base_model(old, new);
+        self.body.replace_base_model(old, new);
+    }
+
+    /// Draws the snake in the middle of the screen, turning a bit further every frame.
+    pub fn draw(&mut self, dt: f32) {
+        self.angle += dt * 0.8;
+        // Turn around the middle of the snake
+        let middle = Self::SPACING * Self::SEGMENTS as f32 / 2.0;
+        let transform =
+            Mat4::from_rotation_y(self.angle) * Mat4::from_translation(vec3(middle, 0.0, 0.0));
+        self.head.base_transform(transform);
+        self.body.base_transform(transform);
+
+        let camera = Camera3D {
+            position: vec3(0.0, 25.0, 70.0),
+            target: vec3(0.0, 0.0, 0.0),
+            up: vec3(0.0, 1.0, 0.0),
+            ..Default::default()
+        };
+        set_camera(&camera);
+        let mut culling = Culling::from_camera(&camera, f32::MAX);
+        culling.enabled = false;
+        self.head.draw(&culling);
+        self.body.draw(&culling);
+        set_default_camera();
+    }
+}
+
+/// What the skin menu shows, reset when the menu is closed.
+pub struct SkinMenu {
+    /// Index into `AssetLibrary::skins`, `None` until the menu is opened.
+    pub shown: Option<usize>,
+    preview: Option<SkinPreview>,
+}
+
+impl SkinMenu {
+    pub fn new() -> Self {
+        Self {
+            shown: None,
+            preview: None,
+        }
+    }
+
+    pub fn close(&mut self) {
+        self.shown = None;
+        self.preview = None;
+    }
+
+    /// Shows another skin, the preview is built again.
+    pub fn show(&mut self, index: usize) {
+        self.shown = Some(index);
+        self.preview = None;
+    }
+
+    pub fn preview(&mut self, library: &AssetLibrary, skin: &SkinEntry) -> &mut SkinPreview {
+        self.preview
+            .get_or_insert_with(|| SkinPreview::new(library, skin))
+    }
+
+    pub fn replace_model(&mut self, old: &Rc<Model3D>, new: &Rc<Model3D>) {
+        if let Some(preview) = &mut self.preview {
+            preview.replace_model(old, new);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn skin(unlock_score: i32) -> SkinEntry {
+        SkinEntry {
+            unlock_score,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_index() {
+        let skins = vec![
+            (String::from("classic"), skin(0)),
+            (String::from("gold"), skin(25)),
+        ];
+        let choice = SkinChoice {
+            file: PathBuf::new(),
+            id: String::from("gold"),
+        };
+        assert_eq!(choice.index(&skins, 30), 1);
+        // Locked again, for example after deleting the scores
+        assert_eq!(choice.index(&skins, 10), 0);
+        let missing = SkinChoice {
+            file: PathBuf::new(),
+            id: String::from("removed"),
+        };
+        assert_eq!(missing.index(&skins, 30), 0);
+    }
+}
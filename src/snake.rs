@@ -1,9 +1,12 @@
 use std::collections::VecDeque;
 
 use crate::draw_utils::SPACE_SIZE;
+use crate::events::{DeathCause, Events, GameEvent};
 use crate::food::{FoodFactory, FoodVariant};
-use crate::models3d::{Model3D, MultiModel};
+use crate::models3d::{Culling, DrawStats, Model3D, MultiModel};
+use crate::orientation::Orientation;
 use macroquad::prelude::*;
+use std::rc::Rc;
 
 /// A function to calculate the modulus of a float value with a given modulus.
 /// It ensures that the result is always non-negative.
@@ -36,67 +39,104 @@ pub fn mod_distance(v1: Vec3, v2: Vec3) -> f32 {
     (dx * dx + dy * dy + dz * dz).sqrt()
 }
 
-pub struct ShnekHead<'a> {
+/// The shortest vector from `from` to `to` when space wraps around.
+/// Every component is in [-SPACE_SIZE / 2, SPACE_SIZE / 2].
+pub fn mod_delta(from: Vec3, to: Vec3) -> Vec3 {
+    fn delta(x1: f32, x2: f32, m: f32) -> f32 {
+        let diff = modulus(x2 - x1, m);
+        if diff > m / 2.0 {
+            diff - m
+        } else {
+            diff
+        }
+    }
+    let m = SPACE_SIZE;
+    vec3(
+        delta(from.x, to.x, m),
+        delta(from.y, to.y, m),
+        delta(from.z, to.z, m),
+    )
+}
+
+/// Distance from `point` to the capsule axis going from `a` to `b`, wrap-aware.
+/// `a` and `b` are expected to be close (less than SPACE_SIZE / 2 apart in every direction).
+pub fn mod_segment_distance(point: Vec3, a: Vec3, b: Vec3) -> f32 {
+    // Unwrap everything around `a`, then it is a normal point-segment distance.
+    let ab = mod_delta(a, b);
+    let ap = mod_delta(a, point);
+    let len_sq = ab.length_squared();
+    if len_sq < 1e-6 {
+        return ap.length();
+    }
+    let t = (ap.dot(ab) / len_sq).clamp(0.0, 1.0);
+    (ap - ab * t).length()
+}
+
+pub struct ShnekHead {
     position: Vec3,
-    direction: Vec3,
-    up: Vec3,
-    model: MultiModel<'a>,
+    orientation: Orientation,
+    model: MultiModel,
     /*
     Position is location within [0, SPACE_SIZE]^3
     Be careful, some things get weird when using modulus on floats.
      */
 }
 
-impl<'a> ShnekHead<'a> {
-    pub fn new(x: f32, y: f32, z: f32, base_model: &'a Model3D) -> Self {
-        let mut model = MultiModel::new(base_model, 3);
-        model.add_transformed(&Mat4::IDENTITY, 0);
+impl ShnekHead {
+    pub fn new(x: f32, y: f32, z: f32, base_model: Rc<Model3D>, repeat: i32) -> Self {
         Self {
             position: vec3(x, y, z),
-            direction: vec3(0.0, 0.0, 0.0),
-            up: vec3(0.0, 0.0, 1.0),
-            model,
+            orientation: Orientation::IDENTITY,
+            model: Self::model(base_model, WHITE, repeat),
         }
     }
 
-    pub fn move_forward(&mut self, distance: f32) {
-        self.position = modulus_vec3(self.position + (self.direction * distance), SPACE_SIZE);
+    /// The head is one instance, moved by the base transform.
+    fn model(base_model: Rc<Model3D>, tint: Color, repeat: i32) -> MultiModel {
+        let mut model = MultiModel::new(base_model, repeat);
+        model.set_tint(tint);
+        model.add_transformed(&Mat4::IDENTITY, 0);
+        model
+    }
+
+    /// Returns the axes the head wrapped around on.
+    pub fn move_forward(&mut self, distance: f32) -> BVec3 {
+        let moved = self.position + (self.orientation.forward() * distance);
+        self.position = modulus_vec3(moved, SPACE_SIZE);
+        (moved - self.position)
+            .abs()
+            .cmpgt(Vec3::splat(SPACE_SIZE / 2.0))
     }
 
     pub fn set_position(&mut self, x: f32, y: f32, z: f32) {
         self.position = vec3(x, y, z);
     }
 
-    pub fn set_direction(&mut self, d: Vec3, up: Vec3) {
-        self.direction = d;
-        self.up = up;
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
     }
 
-    pub fn get_direction(&self) -> Vec3 {
-        self.direction
+    pub fn get_orientation(&self) -> Orientation {
+        self.orientation
     }
 
-    pub fn draw(&mut self) {
-        let right = self.direction.cross(self.up).normalize();
-        let rotation = Mat3::from_cols(self.direction, self.up, right);
-        let transform = Mat4::from_translation(self.position).mul_mat4(&Mat4::from_mat3(rotation));
+    pub fn draw(&mut self, culling: &Culling) -> DrawStats {
+        let transform = self.orientation.to_mat4(self.position);
         self.model.base_transform(transform);
-        self.model.draw();
+        self.model.draw(culling)
     }
 }
 
 struct ShnekSegment {
     /// This is the position of the segment, position is not modulus-ed.
     position: Vec3,
-    forward: Vec3,
-    up: Vec3,
+    orientation: Orientation,
 }
 impl ShnekSegment {
-    pub fn new(position: Vec3, forward: Vec3, up: Vec3) -> Self {
+    pub fn new(position: Vec3, orientation: Orientation) -> Self {
         Self {
             position,
-            forward,
-            up,
+            orientation,
         }
     }
 
@@ -104,9 +144,8 @@ impl ShnekSegment {
         self.position = position;
     }
 
-    pub fn set_direction(&mut self, direction: Vec3, up: Vec3) {
-        self.forward = direction;
-        self.up = up;
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
     }
 
     pub fn get_position(&self) -> Vec3 {
@@ -117,41 +156,49 @@ impl ShnekSegment {
 #[derive(Copy, Clone, Debug)]
 struct HeadSnapshot {
     position: Vec3,
-    direction: Vec3,
-    up: Vec3,
+    orientation: Orientation,
     time: f32,
 }
 
-pub struct Shnek<'a> {
+pub struct Shnek {
     segments: Vec<ShnekSegment>,
-    head: ShnekHead<'a>,
-    base_body_model: &'a Model3D,
+    head: ShnekHead,
+    // Updated in place when drawing, one instance per segment (id is the segment index)
+    body_model: MultiModel,
     // historical positions of the head, used to know where the segments should be
     head_positions: VecDeque<HeadSnapshot>,
     speed: f32,
     pub time_moving: f32,
     time_boosted: f32,
+    boosting: bool,
     pub start_length: usize,
     pub alive: bool,
+    /// Copies of the world the models are drawn with, kept when the skin changes.
+    repeat: i32,
 }
 
-impl<'a> Shnek<'a> {
+impl Shnek {
     const SPACING: f32 = 10.0; // Approximate distance between segments
     const HEAD_SPACE: f32 = 10.0; // Distance between the head and the first segment
+    const COLLISION_RADIUS: f32 = 4.0; // Radius of the capsules between segments
+    const IGNORED_SEGMENTS: usize = 3; // The head can not turn sharp enough to hit these
 
     pub fn new(
-        base_head_model: &'a Model3D,
-        base_body_model: &'a Model3D,
+        base_head_model: Rc<Model3D>,
+        base_body_model: Rc<Model3D>,
         start_length: usize,
+        repeat: i32,
     ) -> Self {
         let mut s = Self {
             segments: Vec::new(),
-            base_body_model,
-            head: ShnekHead::new(0.0, 0.0, 0.0, base_head_model),
+            body_model: MultiModel::new(base_body_model, repeat),
+            head: ShnekHead::new(0.0, 0.0, 0.0, base_head_model, repeat),
+            repeat,
             head_positions: VecDeque::new(),
             speed: 10.0,
             time_moving: 0.0,
             time_boosted: 0.0,
+            boosting: false,
             start_length,
             alive: true,
         };
@@ -175,40 +222,56 @@ impl<'a> Shnek<'a> {
                 };
                 let new_pos = last_segment.get_position()
                     + (last_segment.get_position() - before_last_pos).normalize() * Shnek::SPACING;
-                let forward = (last_segment.position - new_pos).normalize();
-                ShnekSegment::new(new_pos, forward, last_segment.up)
+                let forward = last_segment.position - new_pos;
+                ShnekSegment::new(
+                    new_pos,
+                    Orientation::from_forward_up(forward, last_segment.orientation.up()),
+                )
             }
             None => {
                 let head_pos = self.get_position();
-                let head_dir = self.head.get_direction();
-                let pos = head_pos - head_dir.normalize() * Shnek::HEAD_SPACE;
+                let head_orientation = self.head.get_orientation();
+                let pos = head_pos - head_orientation.forward() * Shnek::HEAD_SPACE;
 
-                ShnekSegment::new(pos, head_dir, self.head.up)
+                ShnekSegment::new(pos, head_orientation)
             }
         };
         self.segments.push(new_segment);
     }
 
-    pub fn pop_segment(&mut self) {
+    pub fn pop_segment(&mut self, events: &mut Events) {
         if self.segments.len() <= self.start_length {
-            self.alive = false;
+            self.die(DeathCause::BadFood, events);
             return;
         }
         self.segments.pop();
+        events.push(GameEvent::SegmentLost);
+    }
+
+    /// Only the first cause of death is reported.
+    fn die(&mut self, cause: DeathCause, events: &mut Events) {
+        if self.alive {
+            self.alive = false;
+            events.push(GameEvent::Died { cause });
+        }
     }
 
-    pub fn move_forward(&mut self, dt: f32) {
+    pub fn move_forward(&mut self, dt: f32, events: &mut Events) {
         // Segments are some time behind the head
         // If there is no suitable position, the oldest one is used
 
         self.time_moving += dt;
 
-        self.head.move_forward(dt * self.speed);
+        let wrapped = self.head.move_forward(dt * self.speed);
+        for axis in 0..3 {
+            if wrapped.test(axis) {
+                events.push(GameEvent::Wrapped { axis });
+            }
+        }
         self.head_positions.push_back(HeadSnapshot {
             position: self.get_position(),
             time: self.time_moving,
-            up: self.head.up,
-            direction: self.head.direction,
+            orientation: self.head.get_orientation(),
         });
 
         let mut j = (self.head_positions.len() - 1) as i32;
@@ -220,13 +283,30 @@ impl<'a> Shnek<'a> {
                 j -= 1;
             }
             if j >= 0 {
+                // Interpolate between the two snapshots around time t
                 let head_snapshot = self.head_positions[j as usize];
-                self.segments[i].set_position(head_snapshot.position);
-                self.segments[i].set_direction(head_snapshot.direction, head_snapshot.up);
+                let (position, orientation) = match self.head_positions.get(j as usize + 1) {
+                    Some(next) if next.time > head_snapshot.time => {
+                        let k = (t - head_snapshot.time) / (next.time - head_snapshot.time);
+                        let delta = mod_delta(head_snapshot.position, next.position);
+                        (
+                            head_snapshot.position + delta * k,
+                            head_snapshot.orientation.slerp(&next.orientation, k),
+                        )
+                    }
+                    _ => (head_snapshot.position, head_snapshot.orientation),
+                };
+                self.segments[i].set_position(position);
+                self.segments[i].set_orientation(orientation);
             } else {
+                // Not enough history, so the segment is placed on a straight line behind
+                // the oldest snapshot (this is how the snake looks when it spawns).
                 let head_snapshot = self.head_positions[0];
-                self.segments[i].set_position(head_snapshot.position);
-                self.segments[i].set_direction(head_snapshot.direction, head_snapshot.up);
+                let behind = (head_snapshot.time - t) * self.speed;
+                self.segments[i].set_position(
+                    head_snapshot.position - head_snapshot.orientation.forward() * behind,
+                );
+                self.segments[i].set_orientation(head_snapshot.orientation);
             }
         }
     }
@@ -235,14 +315,18 @@ impl<'a> Shnek<'a> {
         self.time_moving = 0.0;
         self.alive = true;
         self.time_boosted = 0.0;
+        self.boosting = false;
         self.segments.clear();
         self.head_positions.clear();
         self.set_position(0., 0., 0.);
-        self.set_direction(vec3(1., 0., 0.), vec3(0., 0., 1.));
+        self.set_orientation(Orientation::from_forward_up(
+            vec3(1., 0., 0.),
+            vec3(0., 0., 1.),
+        ));
     }
 
-    pub fn set_direction(&mut self, d: Vec3, up: Vec3) {
-        self.head.set_direction(d, up);
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.head.set_orientation(orientation);
     }
 
     pub fn set_position(&mut self, x: f32, y: f32, z: f32) {
@@ -254,24 +338,41 @@ impl<'a> Shnek<'a> {
     }
 
     pub fn get_camera_position(&self) -> Vec3 {
-        self.head.position + self.head.up * 2.0
+        self.head.position + self.head.orientation.up() * 2.0
     }
 
     pub fn get_length(&self) -> usize {
         self.segments.len()
     }
 
-    pub fn check_boost_and_move(&mut self, dt: f32) {
+    pub fn segment_positions(&self) -> impl Iterator<Item = Vec3> + '_ {
+        self.segments.iter().map(|s| s.get_position())
+    }
+
+    pub fn check_boost_and_move(&mut self, dt: f32, events: &mut Events) {
         if is_key_down(KeyCode::LeftShift) {
-            self.move_forward(dt * 2.);
+            if !self.boosting {
+                events.push(GameEvent::BoostStarted);
+            }
+            self.boosting = true;
+            self.move_forward(dt * 2., events);
             self.time_boosted += dt;
         } else {
+            if self.boosting {
+                events.push(GameEvent::BoostEnded);
+            }
+            self.boosting = false;
             self.time_boosted = 0.0;
-            self.move_forward(dt);
+            self.move_forward(dt, events);
         }
     }
 
-    pub fn check_boost_time(&mut self, food_factory: &mut FoodFactory, start_len: usize) -> bool {
+    pub fn check_boost_time(
+        &mut self,
+        food_factory: &mut FoodFactory,
+        start_len: usize,
+        events: &mut Events,
+    ) {
         if self.time_boosted > 3. && self.segments.len() > start_len {
             let segment = self.segments.pop().unwrap();
             self.time_boosted -= 3.;
@@ -281,45 +382,68 @@ impl<'a> Shnek<'a> {
                 1.0,
                 1,
                 FoodVariant::Poop,
-                segment.up,
-                segment.forward.cross(segment.up),
+                segment.orientation,
                 self.time_moving,
             );
+            events.push(GameEvent::Pooped);
         } else if self.time_boosted > 3. {
-            self.alive = false;
-            return true;
+            self.die(DeathCause::BoostStarvation, events);
         }
-        false
     }
 
-    pub fn check_tail_collision(&mut self) -> bool {
-        if self.time_moving < 2.0 {
-            return false; // 2 s of spawn immunity
+    /// Checks the head against capsules between consecutive segments.
+    /// The first `IGNORED_SEGMENTS` segments behind the head are skipped.
+    pub fn check_tail_collision(&mut self, events: &mut Events) {
+        if self.segments.len() <= Shnek::IGNORED_SEGMENTS {
+            return;
         }
-        for segment in self.segments[1..].iter() {
-            let dist = mod_distance(self.get_position(), segment.get_position());
-            if dist < Shnek::SPACING * 0.8 {
-                self.alive = false;
-                return true; // Collision detected
+        let head = self.get_position();
+        for pair in self.segments[Shnek::IGNORED_SEGMENTS..].windows(2) {
+            let dist = mod_segment_distance(head, pair[0].get_position(), pair[1].get_position());
+            if dist < Shnek::COLLISION_RADIUS {
+                self.die(DeathCause::TailCollision, events);
+                return;
             }
         }
-        false // No collision
     }
 
-    fn create_body_model(&mut self) -> MultiModel<'a> {
-        let mut model = MultiModel::new(self.base_body_model, 3);
+    fn update_body_model(&mut self) {
         for (id, segment) in self.segments.iter().enumerate() {
-            let translation = Mat4::from_translation(segment.get_position());
-            let right = segment.forward.cross(segment.up).normalize();
-            let rotation = Mat4::from_mat3(Mat3::from_cols(segment.forward, segment.up, right));
-            model.add_transformed(&translation.mul_mat4(&rotation), id);
+            self.body_model
+                .add_transformed(&segment.orientation.to_mat4(segment.get_position()), id);
         }
-        model
+        // Segments that were removed since the last frame
+        for id in self.segments.len()..self.body_model.len() {
+            self.body_model.remove_transformed(id);
+        }
+    }
+
+    pub fn draw(&mut self, culling: &Culling) -> DrawStats {
+        self.update_body_model();
+        let mut stats = self.head.draw(culling);
+        stats += self.body_model.draw(culling);
+        stats
+    }
+
+    /// Changes the look of the snake, the colours of the models are multiplied with `tint`.
+    pub fn set_skin(&mut self, head: Rc<Model3D>, body: Rc<Model3D>, tint: Color) {
+        self.head.model = ShnekHead::model(head, tint, self.repeat);
+        // Segments are added again the next time the body is drawn
+        self.body_model = MultiModel::new(body, self.repeat);
+        self.body_model.set_tint(tint);
     }
 
-    pub fn draw(&mut self) {
-        self.head.draw();
-        self.create_body_model().draw(); // This could be cached in pause screen
+    /// Copies of the world drawn in every direction, when the view distance changed.
+    pub fn set_repeat(&mut self, repeat: i32) {
+        self.repeat = repeat;
+        self.head.model.set_repeat(repeat);
+        self.body_model.set_repeat(repeat);
+    }
+
+    /// Switches to a reloaded model, see `MultiModel::replace_base_model`.
+    pub fn replace_model(&mut self, old: &Rc<Model3D>, new: &Rc<Model3D>) {
+        self.head.model.replace_base_model(old, new);
+        self.body_model.replace_base_model(old, new);
     }
 }
 
@@ -327,6 +451,104 @@ impl<'a> Shnek<'a> {
 mod tests {
     use super::*;
 
+    fn empty_model() -> Rc<Model3D> {
+        Rc::new(Model3D { meshes: Vec::new() })
+    }
+
+    fn drain(events: &mut Events) -> Vec<GameEvent> {
+        let drained = events.iter().copied().collect();
+        events.clear();
+        drained
+    }
+
+    #[test]
+    fn test_bad_food_events() {
+        let mut snake = Shnek::new(empty_model(), empty_model(), 3, 1);
+        snake.add_segment();
+        let mut events = Events::new();
+        snake.pop_segment(&mut events);
+        assert_eq!(drain(&mut events), [GameEvent::SegmentLost]);
+        assert_eq!(snake.get_length(), 3);
+
+        // Shrinking below the start length kills, but only once
+        for _ in 0..3 {
+            snake.pop_segment(&mut events);
+        }
+        assert_eq!(
+            drain(&mut events),
+            [GameEvent::Died {
+                cause: DeathCause::BadFood
+            }]
+        );
+        assert!(!snake.alive);
+        assert_eq!(snake.get_length(), 3);
+    }
+
+    #[test]
+    fn test_boost_time_events() {
+        let mut snake = Shnek::new(empty_model(), empty_model(), 3, 1);
+        snake.add_segment();
+        let mut food = FoodFactory::with_models(|_| vec![MultiModel::new(empty_model(), 1)]);
+        let mut events = Events::new();
+        snake.time_boosted = 3.5;
+        snake.check_boost_time(&mut food, 3, &mut events);
+        assert_eq!(drain(&mut events), [GameEvent::Pooped]);
+        assert_eq!(snake.get_length(), 3);
+
+        // Nothing left to turn into poop
+        snake.time_boosted = 3.5;
+        snake.check_boost_time(&mut food, 3, &mut events);
+        snake.check_boost_time(&mut food, 3, &mut events);
+        assert_eq!(
+            drain(&mut events),
+            [GameEvent::Died {
+                cause: DeathCause::BoostStarvation
+            }]
+        );
+    }
+
+    #[test]
+    fn test_tail_collision() {
+        let mut snake = Shnek::new(empty_model(), empty_model(), 6, 1);
+        snake.set_position(30., 30., 30.);
+        // The last capsule crosses the border of the space at y = 60
+        let positions = [
+            vec3(20., 30., 30.),
+            vec3(10., 30., 30.),
+            vec3(30., 30., 31.),
+            vec3(30., 45., 30.),
+            vec3(30., 55., 30.),
+            vec3(30., 5., 30.),
+        ];
+        for (segment, position) in snake.segments.iter_mut().zip(positions) {
+            segment.set_position(position);
+        }
+        let mut events = Events::new();
+
+        // Touching the last ignored segment is fine
+        assert!(
+            snake.segments[Shnek::IGNORED_SEGMENTS - 1]
+                .get_position()
+                .distance(snake.get_position())
+                < Shnek::COLLISION_RADIUS
+        );
+        snake.check_tail_collision(&mut events);
+        assert!(drain(&mut events).is_empty());
+        assert!(snake.alive);
+
+        // Just across the border from the capsule between 55 and 5
+        snake.set_position(31., 1., 30.);
+        snake.check_tail_collision(&mut events);
+        snake.check_tail_collision(&mut events);
+        assert_eq!(
+            drain(&mut events),
+            [GameEvent::Died {
+                cause: DeathCause::TailCollision
+            }]
+        );
+        assert!(!snake.alive);
+    }
+
     fn almost_eq(a: Vec3, b: Vec3) -> bool {
         (a.x - b.x).abs() < 1e-3 && (a.y - b.y).abs() < 1e-3 && (a.z - b.z).abs() < 1e-3
     }
@@ -382,11 +604,11 @@ mod tests {
     fn test_mod_distance() {
         assert!(mod_distance(vec3(10., 20., 30.), vec3(70., 80., 90.)) < 1e-3);
         assert!(mod_distance(vec3(10.2, 33.22, 3.1), vec3(5.6, 20.0, 49.3)) - 48.273889 < 1e-3);
-        assert!(mod_distance(vec3(3.5, 48.6, 0.), vec3(118.3, 61.2, -90.)) - 32.951479 < 1e-3);
+        assert!(mod_distance(vec3(3.5, 48.6, 0.), vec3(118.3, 61.2, -90.)) - 32.951_48 < 1e-3);
         assert!(
             mod_distance(
-                vec3(0.33333333, 0.44444444, 0.55555555),
-                vec3(-0.33333333, -0.44444444, -0.55555555)
+                vec3(0.333_333_3, 0.444_444_4, 0.555_555_6),
+                vec3(-0.333_333_3, -0.444_444_4, -0.555_555_6)
             ) - 1.571348
                 < 1e-3
         );
@@ -397,4 +619,40 @@ mod tests {
             ) < 1e-3
         );
     }
+
+    #[test]
+    fn test_mod_delta() {
+        assert!(almost_eq(
+            mod_delta(vec3(1., 2., 3.), vec3(4., 6., 8.)),
+            vec3(3., 4., 5.)
+        ));
+        assert!(almost_eq(
+            mod_delta(vec3(58., 1., 30.), vec3(2., 59., 30.)),
+            vec3(4., -2., 0.)
+        ));
+        assert!(almost_eq(
+            mod_delta(vec3(-1., 121., 0.), vec3(1., 0., 0.)),
+            vec3(2., -1., 0.)
+        ));
+    }
+
+    #[test]
+    fn test_mod_segment_distance() {
+        let a = vec3(10., 10., 10.);
+        let b = vec3(20., 10., 10.);
+        // Next to the middle, past the ends and exactly on the axis
+        assert!((mod_segment_distance(vec3(15., 13., 10.), a, b) - 3.0).abs() < 1e-3);
+        assert!((mod_segment_distance(vec3(25., 10., 10.), a, b) - 5.0).abs() < 1e-3);
+        assert!((mod_segment_distance(vec3(6., 13., 10.), a, b) - 5.0).abs() < 1e-3);
+        assert!(mod_segment_distance(vec3(12., 10., 10.), a, b) < 1e-3);
+
+        // A capsule crossing the border of the space
+        let a = vec3(55., 30., 30.);
+        let b = vec3(5., 30., 30.);
+        assert!((mod_segment_distance(vec3(0., 32., 30.), a, b) - 2.0).abs() < 1e-3);
+        assert!((mod_segment_distance(vec3(30., 30., 30.), a, b) - 25.0).abs() < 1e-3);
+
+        // Degenerate capsule is a sphere
+        assert!((mod_segment_distance(vec3(55., 30., 33.), a, a) - 3.0).abs() < 1e-3);
+    }
 }
@@ -0,0 +1,209 @@
+/// The screens of the game.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum GameState {
+    MainMenu,
+    Running,
+    Paused,
+    GameOver,
+    Score,
+    Skins,
+    Stats,
+    Achievements,
+    Profiles,
+    /// Typing the name of a new profile.
+    NewProfile,
+    /// Typing the name for a high score.
+    NameEntry,
+}
+
+/// A change of screens requested by a menu or the game.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Transition {
+    /// Opens a screen on top of the current one.
+    Push(GameState),
+    /// Closes the current screen, back to the one below.
+    Pop,
+    /// Closes the current screen and opens another one in its place.
+    Replace(GameState),
+    /// Closes everything above the main menu and opens a screen, like starting a new game.
+    Restart(GameState),
+}
+
+/// Run by the main loop when a transition is applied, in this order.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Hook {
+    Exit(GameState),
+    Enter(GameState),
+}
+
+impl GameState {
+    /// The transitions that make sense from this screen, everything else is ignored.
+    fn allows(self, transition: Transition) -> bool {
+        use GameState::*;
+        use Transition::*;
+        matches!(
+            (self, transition),
+            (
+                MainMenu,
+                Push(Running | Score | Skins | Stats | Achievements | Profiles)
+            ) | (Running, Push(Paused) | Replace(GameOver))
+                | (Paused, Pop | Restart(Running))
+                | (GameOver, Push(Score | NameEntry) | Restart(Running))
+                | (Profiles, Push(NewProfile) | Pop)
+                | (
+                    Score | Skins | Stats | Achievements | NewProfile | NameEntry,
+                    Pop
+                )
+        )
+    }
+
+    /// Keys are typed into a name, they are not shortcuts.
+    pub fn takes_text(self) -> bool {
+        matches!(self, GameState::NewProfile | GameState::NameEntry)
+    }
+
+    /// What Escape and Space do on this screen.
+    pub fn back(self) -> Option<Transition> {
+        match self {
+            GameState::Running => Some(Transition::Push(GameState::Paused)),
+            GameState::Paused
+            | GameState::Score
+            | GameState::Skins
+            | GameState::Stats
+            | GameState::Achievements
+            | GameState::Profiles => Some(Transition::Pop),
+            // Space is typed, these screens close themselves on Escape
+            GameState::NewProfile | GameState::NameEntry => None,
+            GameState::MainMenu | GameState::GameOver => None,
+        }
+    }
+}
+
+/** A stack of screens with the main menu at the bottom. The screen on top is
+the current one, screens below it come back when it is closed, so Back always
+returns to where the player came from. Screens request transitions while they
+are drawn, the main loop applies them at the end of the frame and runs the
+enter and exit hooks.
+*/
+pub struct StateMachine {
+    stack: Vec<GameState>,
+    requested: Vec<Transition>,
+}
+
+impl StateMachine {
+    pub fn new() -> Self {
+        Self {
+            stack: vec![GameState::MainMenu],
+            requested: Vec::new(),
+        }
+    }
+
+    pub fn current(&self) -> GameState {
+        *self.stack.last().unwrap()
+    }
+
+    pub fn request(&mut self, transition: Transition) {
+        self.requested.push(transition);
+    }
+
+    /// Applies the requested transitions that are allowed and returns the hooks to run.
+    pub fn apply(&mut self) -> Vec<Hook> {
+        let mut hooks = Vec::new();
+        for transition in std::mem::take(&mut self.requested) {
+            let current = self.current();
+            if !current.allows(transition) {
+                continue; // For example a button and a key in the same frame
+            }
+            match transition {
+                Transition::Push(state) => {
+                    self.stack.push(state);
+                    hooks.push(Hook::Enter(state));
+                }
+                Transition::Pop => {
+                    self.stack.pop();
+                    hooks.push(Hook::Exit(current));
+                }
+                Transition::Replace(state) => {
+                    self.stack.pop();
+                    self.stack.push(state);
+                    hooks.push(Hook::Exit(current));
+                    hooks.push(Hook::Enter(state));
+                }
+                Transition::Restart(state) => {
+                    while self.stack.len() > 1 {
+                        hooks.push(Hook::Exit(self.stack.pop().unwrap()));
+                    }
+                    self.stack.push(state);
+                    hooks.push(Hook::Enter(state));
+                }
+            }
+        }
+        hooks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use GameState::*;
+
+    #[test]
+    fn test_pause_and_resume() {
+        let mut states = StateMachine::new();
+        states.request(Transition::Push(Running));
+        assert_eq!(states.apply(), vec![Hook::Enter(Running)]);
+        states.request(Running.back().unwrap());
+        states.apply();
+        assert_eq!(states.stack, vec![MainMenu, Running, Paused]);
+        // Resuming does not enter the game again
+        states.request(Paused.back().unwrap());
+        assert_eq!(states.apply(), vec![Hook::Exit(Paused)]);
+        assert_eq!(states.current(), Running);
+    }
+
+    #[test]
+    fn test_back_from_score() {
+        // Opened from the main menu, back goes to the main menu
+        let mut states = StateMachine::new();
+        states.request(Transition::Push(Score));
+        states.request(Transition::Pop);
+        states.apply();
+        assert_eq!(states.current(), MainMenu);
+
+        // Opened after the game, back goes to the game over screen
+        states.request(Transition::Push(Running));
+        states.request(Transition::Replace(GameOver));
+        states.request(Transition::Push(Score));
+        states.request(Transition::Pop);
+        states.apply();
+        assert_eq!(states.current(), GameOver);
+    }
+
+    #[test]
+    fn test_restart() {
+        let mut states = StateMachine::new();
+        states.request(Transition::Push(Running));
+        states.request(Transition::Push(Paused));
+        states.apply();
+        states.request(Transition::Restart(Running));
+        assert_eq!(
+            states.apply(),
+            vec![
+                Hook::Exit(Paused),
+                Hook::Exit(Running),
+                Hook::Enter(Running)
+            ]
+        );
+        assert_eq!(states.stack, vec![MainMenu, Running]);
+    }
+
+    #[test]
+    fn test_not_allowed() {
+        let mut states = StateMachine::new();
+        // The main menu can't be closed and the game over screen needs a game
+        states.request(Transition::Pop);
+        states.request(Transition::Replace(GameOver));
+        assert!(states.apply().is_empty());
+        assert_eq!(states.current(), MainMenu);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::events::DeathCause;
+use crate::food::FoodVariant;
+use crate::storage::{load_toml, save_toml, write_file};
+use crate::summary::{format_time, RunSummary};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Deaths {
+    pub tail_collision: u32,
+    pub boost_starvation: u32,
+    pub bad_food: u32,
+}
+
+/** Statistics over all games of a profile, saved in `stats.toml` in the profile directory.
+A game counts when it ends, by dying or by a reset.
+*/
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LifetimeStats {
+    pub games_played: u32,
+    /// Seconds.
+    pub time_played: f32,
+    pub apples: u32,
+    pub bad_apples: u32,
+    pub poop: u32,
+    pub deaths: Deaths,
+    pub longest_snake: usize,
+    /// Seconds.
+    pub longest_survival: f32,
+    pub distance: f32,
+}
+
+impl LifetimeStats {
+    const FILE: &'static str = "stats.toml";
+
+    pub fn load(dir: &Path) -> Self {
+        load_toml(&dir.join(Self::FILE))
+    }
+
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        save_toml(&dir.join(Self::FILE), self)
+    }
+
+    pub fn add_run(&mut self, run: &RunSummary) {
+        self.games_played += 1;
+        self.time_played += run.time_alive;
+        self.apples += run.eaten(FoodVariant::Normal);
+        self.bad_apples += run.eaten(FoodVariant::Bad);
+        self.poop += run.eaten(FoodVariant::Poop);
+        match run.cause {
+            Some(DeathCause::TailCollision) => self.deaths.tail_collision += 1,
+            Some(DeathCause::BoostStarvation) => self.deaths.boost_starvation += 1,
+            Some(DeathCause::BadFood) => self.deaths.bad_food += 1,
+            None => {} // Reset before dying
+        }
+        self.longest_snake = self.longest_snake.max(run.max_length);
+        self.longest_survival = self.longest_survival.max(run.time_alive);
+        self.distance += run.distance;
+    }
+
+    /// Names and values for the stats screen.
+    pub fn rows(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("games played", self.games_played.to_string()),
+            ("time played", format_time(self.time_played)),
+            ("apples eaten", self.apples.to_string()),
+            ("bad apples eaten", self.bad_apples.to_string()),
+            ("poop eaten", self.poop.to_string()),
+            ("bit own tail", self.deaths.tail_collision.to_string()),
+            ("boosted to death", self.deaths.boost_starvation.to_string()),
+            ("shrank from bad food", self.deaths.bad_food.to_string()),
+            ("longest snake", self.longest_snake.to_string()),
+            ("longest survival", format_time(self.longest_survival)),
+            ("distance", self.distance.round().to_string()),
+        ]
+    }
+
+    /// A header and one line of values, so exports of several players can be pasted together.
+    /// Times are in seconds.
+    pub fn to_csv(&self, name: &str) -> String {
+        let header = [
+            "name",
+            "games_played",
+            "time_played",
+            "apples",
+            "bad_apples",
+            "poop",
+            "deaths_tail_collision",
+            "deaths_boost_starvation",
+            "deaths_bad_food",
+            "longest_snake",
+            "longest_survival",
+            "distance",
+        ];
+        let values = [
+            name.to_string(),
+            self.games_played.to_string(),
+            self.time_played.round().to_string(),
+            self.apples.to_string(),
+            self.bad_apples.to_string(),
+            self.poop.to_string(),
+            self.deaths.tail_collision.to_string(),
+            self.deaths.boost_starvation.to_string(),
+            self.deaths.bad_food.to_string(),
+            self.longest_snake.to_string(),
+            self.longest_survival.round().to_string(),
+            self.distance.round().to_string(),
+        ];
+        format!("{}\n{}\n", header.join(","), values.join(","))
+    }
+
+    pub fn to_json(&self, name: &str) -> String {
+        #[derive(Serialize)]
+        struct Export<'a> {
+            name: &'a str,
+            #[serde(flatten)]
+            stats: &'a LifetimeStats,
+        }
+        serde_json::to_string_pretty(&Export { name, stats: self }).expect("Failed to write stats")
+    }
+
+    /// Writes `stats.csv` and `stats.json` into the profile directory.
+    pub fn export(&self, dir: &Path, name: &str) -> io::Result<()> {
+        write_file(&dir.join("stats.csv"), &self.to_csv(name))?;
+        write_file(&dir.join("stats.json"), &self.to_json(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{Events, GameEvent};
+    use macroquad::prelude::vec3;
+
+    #[test]
+    fn test_add_run() {
+        let mut run = RunSummary::new(1, 3);
+        let mut events = Events::new();
+        events.push(GameEvent::FoodEaten {
+            variant: FoodVariant::Bad,
+            quality: 1,
+        });
+        events.push(GameEvent::Died {
+            cause: DeathCause::TailCollision,
+        });
+        run.record(&events, vec3(0.0, 0.0, 0.0), 5, 2.0);
+
+        let mut stats = LifetimeStats::default();
+        stats.add_run(&run);
+        stats.add_run(&RunSummary::new(2, 3));
+        assert_eq!(stats.games_played, 2);
+        assert_eq!(stats.bad_apples, 1);
+        assert_eq!(stats.deaths.tail_collision, 1);
+        assert_eq!(stats.longest_snake, 5);
+        assert_eq!(stats.longest_survival, 2.0);
+    }
+
+    #[test]
+    fn test_exports() {
+        let stats = LifetimeStats {
+            games_played: 3,
+            deaths: Deaths {
+                bad_food: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let csv = stats.to_csv("Anna");
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].split(',').count(), lines[1].split(',').count());
+        assert!(lines[1].starts_with("Anna,3,"));
+
+        let json: LifetimeStats = serde_json::from_str(&stats.to_json("Anna")).unwrap();
+        assert_eq!(json, stats);
+        let text = toml::to_string(&stats).unwrap();
+        assert_eq!(toml::from_str::<LifetimeStats>(&text).unwrap(), stats);
+    }
+}
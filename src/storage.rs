@@ -0,0 +1,109 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where the game saved its files before there was a data directory.
+const OLD_DIR: &str = "assets";
+/// Used without a user directory, next to the assets but not in them.
+const FALLBACK_DIR: &str = "shnek-data";
+/// Files and directories that are moved from `OLD_DIR` on the first start.
+const OLD_ENTRIES: [&str; 7] = [
+    "scores.txt",
+    "profiles.toml",
+    "profiles",
+    "settings.toml",
+    "skin.txt",
+    "stats.toml",
+    "achievements.toml",
+];
+
+/** Where scores, profiles and everything else the game writes is saved. This
+is a directory of the user, so the game can be started from anywhere and never
+writes next to the executable. Without one (no home directory) `shnek-data` in
+the working directory is used. Never an assets directory: a mod's directory is
+not the place for scores, and in development mode it is watched for changes.
+*/
+pub fn data_dir() -> PathBuf {
+    let dir = user_data_dir().unwrap_or_else(|| PathBuf::from(FALLBACK_DIR));
+    if !dir.exists() {
+        move_old_files(Path::new(OLD_DIR), &dir);
+    }
+    dir
+}
+
+/// The usual place for application data on each system.
+fn user_data_dir() -> Option<PathBuf> {
+    let var = |name: &str| env::var_os(name).filter(|value| !value.is_empty());
+    let base = if cfg!(windows) {
+        var("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        var("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    base.map(|base| base.join("shnek"))
+}
+
+/// Moves what an older version saved in `old` into the new directory `new`.
+/// Nothing is lost if it fails, the files stay where they were.
+fn move_old_files(old: &Path, new: &Path) {
+    if old == new || fs::create_dir_all(new).is_err() {
+        return;
+    }
+    for entry in OLD_ENTRIES {
+        let path = old.join(entry);
+        if path.exists() {
+            let _ = fs::rename(&path, new.join(entry));
+        }
+    }
+}
+
+/// Writes `text` to `path`, the directory is created first, like for a new profile.
+pub fn write_file(path: &Path, text: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)
+}
+
+/// Missing or broken files give the defaults, saved files are not worth an error screen.
+pub fn load_toml<T: DeserializeOwned + Default>(path: &Path) -> T {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|text| toml::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// Saves `value` as TOML. Failing to save is not worth stopping the game for,
+/// callers report the error and go on.
+pub fn save_toml<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    let text = toml::to_string(value).map_err(io::Error::other)?;
+    write_file(path, &text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_move_old_files() {
+        let dir = env::temp_dir().join(format!("shnek-storage-{}", std::process::id()));
+        let (old, new) = (dir.join("assets"), dir.join("data"));
+        fs::create_dir_all(old.join("profiles/Anna")).unwrap();
+        fs::write(old.join("scores.txt"), "12").unwrap();
+        fs::write(old.join("manifest.toml"), "").unwrap();
+
+        move_old_files(&old, &new);
+        assert_eq!(fs::read_to_string(new.join("scores.txt")).unwrap(), "12");
+        assert!(new.join("profiles/Anna").is_dir());
+        // Assets stay where they are
+        assert!(old.join("manifest.toml").exists());
+        assert!(!new.join("manifest.toml").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+use crate::events::{DeathCause, Events, GameEvent};
+use crate::food::FoodVariant;
+use crate::snake::mod_distance;
+use macroquad::prelude::*;
+use std::collections::HashMap;
+
+/** What happened in one game, shown on the game over screen. Recorded
+every frame while the game runs, from the gameplay events and the snake.
+*/
+pub struct RunSummary {
+    /// Food spawns the same way when a game is started with the same seed.
+    pub seed: u64,
+    pub cause: Option<DeathCause>,
+    pub final_length: usize,
+    pub max_length: usize,
+    eaten: HashMap<FoodVariant, u32>,
+    pub distance: f32,
+    pub time_alive: f32,
+    pub boost_time: f32,
+    /// Longest time without boosting.
+    pub unboosted_time: f32,
+    since_boost: f32,
+    wrapped: [bool; 3],
+    boosting: bool,
+    last_position: Option<Vec3>,
+}
+
+impl RunSummary {
+    pub fn new(seed: u64, start_length: usize) -> Self {
+        Self {
+            seed,
+            cause: None,
+            final_length: start_length,
+            max_length: start_length,
+            eaten: HashMap::new(),
+            distance: 0.0,
+            time_alive: 0.0,
+            boost_time: 0.0,
+            unboosted_time: 0.0,
+            since_boost: 0.0,
+            wrapped: [false; 3],
+            boosting: false,
+            last_position: None,
+        }
+    }
+
+    /// Adds a frame of the game, `position` and `length` are the snake's after the frame.
+    pub fn record(&mut self, events: &Events, position: Vec3, length: usize, dt: f32) {
+        for event in events.iter() {
+            match *event {
+                GameEvent::FoodEaten { variant, .. } => {
+                    *self.eaten.entry(variant).or_default() += 1
+                }
+                GameEvent::BoostStarted => self.boosting = true,
+                GameEvent::BoostEnded => self.boosting = false,
+                GameEvent::Wrapped { axis } => self.wrapped[axis] = true,
+                GameEvent::Died { cause } => {
+                    self.cause.get_or_insert(cause);
+                }
+                _ => {}
+            }
+        }
+        self.time_alive += dt;
+        if self.boosting {
+            self.boost_time += dt;
+            self.since_boost = 0.0;
+        } else {
+            self.since_boost += dt;
+            self.unboosted_time = self.unboosted_time.max(self.since_boost);
+        }
+        // Small steps, so the shortest way through the wrapped space is the way the snake went
+        if let Some(last_position) = self.last_position {
+            self.distance += mod_distance(last_position, position);
+        }
+        self.last_position = Some(position);
+        self.final_length = length;
+        self.max_length = self.max_length.max(length);
+    }
+
+    pub fn eaten(&self, variant: FoodVariant) -> u32 {
+        self.eaten.get(&variant).copied().unwrap_or(0)
+    }
+
+    /// On how many of the three axes the snake went around the cube.
+    pub fn axes_wrapped(&self) -> usize {
+        self.wrapped.iter().filter(|wrapped| **wrapped).count()
+    }
+}
+
+/// Minutes and seconds, like 2:05.
+pub fn format_time(seconds: f32) -> String {
+    let seconds = seconds as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// A seed for a new game.
+pub fn new_seed() -> u64 {
+    (macroquad::miniquad::date::now() * 1000.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let mut summary = RunSummary::new(7, 3);
+        let mut events = Events::new();
+        events.push(GameEvent::FoodEaten {
+            variant: FoodVariant::Normal,
+            quality: 1,
+        });
+        events.push(GameEvent::BoostStarted);
+        events.push(GameEvent::Wrapped { axis: 2 });
+        summary.record(&events, vec3(0.0, 0.0, 0.0), 4, 0.5);
+
+        events.clear();
+        events.push(GameEvent::BoostEnded);
+        events.push(GameEvent::Died {
+            cause: DeathCause::BadFood,
+        });
+        events.push(GameEvent::Died {
+            cause: DeathCause::TailCollision,
+        });
+        // Across the edge of the space, only a short way
+        summary.record(&events, vec3(59.0, 0.0, 0.0), 2, 0.5);
+
+        assert_eq!(summary.eaten(FoodVariant::Normal), 1);
+        assert_eq!(summary.eaten(FoodVariant::Poop), 0);
+        assert_eq!(summary.cause, Some(DeathCause::BadFood));
+        assert_eq!(summary.boost_time, 0.5);
+        assert_eq!(summary.unboosted_time, 0.5);
+        assert_eq!(summary.axes_wrapped(), 1);
+        assert_eq!(summary.time_alive, 1.0);
+        assert!((summary.distance - 1.0).abs() < 1e-4);
+        assert_eq!((summary.final_length, summary.max_length), (2, 4));
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0.4), "0:00");
+        assert_eq!(format_time(125.9), "2:05");
+    }
+}
@@ -0,0 +1,56 @@
+use macroquad::prelude::*;
+use std::collections::VecDeque;
+use std::io;
+
+/** Messages on top of the screen like "Achievement unlocked" or a file that
+could not be saved, shown for a few seconds each, newer ones below older ones.
+*/
+pub struct Toasts {
+    shown: VecDeque<(String, f32)>,
+}
+
+impl Toasts {
+    const TIME: f32 = 4.0;
+    const FADE_TIME: f32 = 0.5;
+
+    pub fn new() -> Self {
+        Self {
+            shown: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, text: String) {
+        self.shown.push_back((text, 0.0));
+    }
+
+    /// Shown on screen, the game goes on without the saved file.
+    pub fn report_save_error(&mut self, what: &str, result: io::Result<()>) {
+        if let Err(error) = result {
+            eprintln!("failed to save {}: {}", what, error);
+            self.push(format!("Could not save {}: {}", what, error));
+        }
+    }
+
+    pub fn draw(&mut self, dt: f32) {
+        for (_, age) in self.shown.iter_mut() {
+            *age += dt;
+        }
+        self.shown.retain(|(_, age)| *age < Self::TIME);
+        for (i, (text, age)) in self.shown.iter().enumerate() {
+            let alpha = ((Self::TIME - age) / Self::FADE_TIME).min(1.0);
+            let size = measure_text(text, None, 32, 1.0);
+            let width = size.width + 40.0;
+            let x = (screen_width() - width) / 2.0;
+            let y = 20.0 + i as f32 * 70.0;
+            draw_rectangle(x, y, width, 56.0, Color::new(0.0, 0.0, 0.0, 0.7 * alpha));
+            draw_rectangle_lines(x, y, width, 56.0, 3.0, Color::new(1.0, 0.84, 0.0, alpha));
+            draw_text(
+                text,
+                x + 20.0,
+                y + 38.0,
+                32.0,
+                Color::new(1.0, 1.0, 1.0, alpha),
+            );
+        }
+    }
+}